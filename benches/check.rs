@@ -0,0 +1,81 @@
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::MockLookup;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use nsdetect::dispatch::{dispatch, DispatchOptions};
+use nsdetect::single_flight::SingleFlight;
+use nsdetect::{inspect_domain_async, InspectOptions};
+use std::convert::Infallible;
+use std::net::Ipv4Addr;
+use std::sync::atomic::AtomicBool;
+use tokio::runtime::Runtime;
+
+const DOMAINS: usize = 10_000;
+
+/// The dispatch loop of the async mode: every domain checked through a
+/// single-flight resolver, with at most `concurrency` checks at a time.
+fn check_all(
+    io_loop: &Runtime,
+    resolver: &MockLookup,
+    domains: Vec<String>,
+    concurrency: usize,
+) -> usize {
+    let resolver = &SingleFlight::new(resolver);
+    let options = &InspectOptions::default();
+    let dispatch_options = DispatchOptions {
+        concurrency,
+        ..DispatchOptions::default()
+    };
+    let input = domains.into_iter().map(|domain| Ok((domain, None)));
+    let mut checked = 0;
+    io_loop
+        .block_on(dispatch(
+            input,
+            &dispatch_options,
+            &AtomicBool::new(false),
+            |domain| async move { inspect_domain_async(&domain, resolver, options).await },
+            |_, _, _| {
+                checked += 1;
+                Ok::<_, Infallible>(())
+            },
+        ))
+        .unwrap();
+    checked
+}
+
+fn check_async(c: &mut Criterion) {
+    let io_loop = Runtime::new().unwrap();
+    // the mock answers every query at once, so that only the work of
+    // nsdetect itself is measured. Every third domain doesn't exist, the
+    // others have an address
+    let mut resolver = MockLookup::default();
+    let domains: Vec<String> = (0..DOMAINS)
+        .map(|i| match i % 3 {
+            0 => format!("gone{}.example.com", i),
+            _ => {
+                let domain = format!("www{}.example.com", i);
+                resolver
+                    .addresses
+                    .insert(domain.clone(), Ipv4Addr::new(192, 0, 2, 1));
+                domain
+            }
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("check_async");
+    group.throughput(Throughput::Elements(DOMAINS as u64));
+    for concurrency in [1, 16, 128, 1024] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(concurrency),
+            &concurrency,
+            |b, &concurrency| {
+                b.iter(|| check_all(&io_loop, &resolver, domains.clone(), concurrency))
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, check_async);
+criterion_main!(benches);
@@ -0,0 +1,186 @@
+use crate::direct::DirectOptions;
+use crate::lookup::{AsyncDnsLookup, DnsLookup};
+use crate::psl::registrable_domain;
+use crate::{deserialize_response_code, serialize_response_code};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::net::IpAddr;
+use tracing::{debug, trace};
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::lookup::Lookup;
+use trust_dns_resolver::proto::op::{Message, ResponseCode};
+use trust_dns_resolver::proto::rr::{RData, RecordType};
+
+/// A name server the domain is delegated to that doesn't answer for it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LameNameServer {
+    /// The name of the name server, as listed in the domain's NS records
+    pub name: String,
+    /// The address the name server was queried at
+    pub ip: IpAddr,
+    /// The response code it answered the SOA query of the domain with, None if
+    /// it answered without being authoritative for the domain
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_response_code",
+        deserialize_with = "deserialize_response_code"
+    )]
+    pub response_code: Option<ResponseCode>,
+}
+
+impl Display for LameNameServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self.response_code {
+            Some(code) if code != ResponseCode::NoError => {
+                write!(f, "lame name server {} answered {:?}", self.name, code)
+            }
+            _ => write!(f, "lame name server {} isn't authoritative", self.name),
+        }
+    }
+}
+
+/// Queries each of the name servers `domain` is delegated to directly for the
+/// SOA record of the domain, and returns the first one that answers with an
+/// error like REFUSED or SERVFAIL, or without the authoritative answer flag.
+pub fn lame_name_server(
+    domain: &str,
+    resolver: &impl DnsLookup,
+    options: &DirectOptions,
+) -> Option<LameNameServer> {
+    for name in name_servers(&resolver.lookup(domain, RecordType::NS)) {
+        let ips = match resolver.lookup_ip(name.as_str()) {
+            Ok(lookup) => lookup.iter().collect::<Vec<_>>(),
+            Err(_) => continue,
+        };
+        for ip in ips {
+            trace!(domain, name_server = name, %ip, "querying name server directly");
+            let soa = resolver.query_name_server(ip, domain, RecordType::SOA, options);
+            if let Some(lame) = lame(domain, &name, ip, &soa) {
+                return Some(lame);
+            }
+        }
+    }
+
+    None
+}
+
+/// The asynchronous version of `lame_name_server`.
+pub async fn lame_name_server_async(
+    domain: &str,
+    resolver: &impl AsyncDnsLookup,
+    options: &DirectOptions,
+) -> Option<LameNameServer> {
+    for name in name_servers(&resolver.lookup(domain, RecordType::NS).await) {
+        let ips = match resolver.lookup_ip(name.as_str()).await {
+            Ok(lookup) => lookup.iter().collect::<Vec<_>>(),
+            Err(_) => continue,
+        };
+        for ip in ips {
+            trace!(domain, name_server = name, %ip, "querying name server directly");
+            let soa = resolver
+                .query_name_server(ip, domain, RecordType::SOA, options)
+                .await;
+            if let Some(lame) = lame(domain, &name, ip, &soa) {
+                return Some(lame);
+            }
+        }
+    }
+
+    None
+}
+
+/// Looks up the registrable domain of each name server `domain` is delegated
+/// to, and returns the first of them that doesn't exist, which means anyone
+/// can register it and take over the name server.
+pub fn unregistered_ns_domain(domain: &str, resolver: &impl DnsLookup) -> Option<String> {
+    for ns_domain in ns_domains(&resolver.lookup(domain, RecordType::NS)) {
+        trace!(
+            domain,
+            ns_domain,
+            "checking if the name server domain exists"
+        );
+        if is_nxdomain(&resolver.lookup(ns_domain.as_str(), RecordType::SOA)) {
+            debug!(domain, ns_domain, "name server domain isn't registered");
+            return Some(ns_domain);
+        }
+    }
+
+    None
+}
+
+/// The asynchronous version of `unregistered_ns_domain`.
+pub async fn unregistered_ns_domain_async(
+    domain: &str,
+    resolver: &impl AsyncDnsLookup,
+) -> Option<String> {
+    for ns_domain in ns_domains(&resolver.lookup(domain, RecordType::NS).await) {
+        trace!(
+            domain,
+            ns_domain,
+            "checking if the name server domain exists"
+        );
+        if is_nxdomain(&resolver.lookup(ns_domain.as_str(), RecordType::SOA).await) {
+            debug!(domain, ns_domain, "name server domain isn't registered");
+            return Some(ns_domain);
+        }
+    }
+
+    None
+}
+
+/// The registrable domains of the name servers in the answer to an NS query, without duplicates.
+fn ns_domains(lookup: &Result<Lookup, ResolveError>) -> Vec<String> {
+    let mut domains: Vec<String> = vec![];
+    for domain in name_servers(lookup)
+        .iter()
+        .filter_map(|name| registrable_domain(name))
+    {
+        if !domains.contains(&domain) {
+            domains.push(domain);
+        }
+    }
+    domains
+}
+
+fn is_nxdomain(lookup: &Result<Lookup, ResolveError>) -> bool {
+    matches!(lookup, Err(err) if matches!(err.kind(), ResolveErrorKind::NoRecordsFound { response_code, .. } if *response_code == ResponseCode::NXDomain))
+}
+
+/// The names in the answer to an NS query.
+fn name_servers(lookup: &Result<Lookup, ResolveError>) -> Vec<String> {
+    match lookup {
+        Ok(lookup) => lookup
+            .record_iter()
+            .filter_map(|r| match r.data() {
+                Some(RData::NS(name)) => Some(name.to_ascii()),
+                _ => None,
+            })
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// Maps the answer of a name server to the SOA query of `domain` to the
+/// problem with it, if there is one. Name servers that don't answer at all
+/// aren't reported, like timeouts aren't for the lookups of the domains.
+fn lame(
+    domain: &str,
+    name: &str,
+    ip: IpAddr,
+    soa: &Result<Message, ResolveError>,
+) -> Option<LameNameServer> {
+    let answer = soa.as_ref().ok()?;
+    let response_code = match answer.response_code() {
+        ResponseCode::NoError if answer.authoritative() => return None,
+        ResponseCode::NoError => None,
+        response_code => Some(response_code),
+    };
+
+    debug!(domain, name_server = name, %ip, ?response_code, "lame delegation");
+    Some(LameNameServer {
+        name: name.strip_suffix('.').unwrap_or(name).to_string(),
+        ip,
+        response_code,
+    })
+}
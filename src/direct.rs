@@ -0,0 +1,185 @@
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::proto::op::{Message, MessageType, OpCode, Query};
+use trust_dns_resolver::proto::rr::{Name, RecordType};
+
+/// Name servers that are queried directly are the authoritative ones, which
+/// always listen on the standard port.
+const PORT: u16 = 53;
+
+/// Big enough for any answer over UDP, with or without EDNS.
+const MAX_UDP_SIZE: usize = 4096;
+
+/// How the name servers are queried directly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirectOptions {
+    /// The address to send the queries from, instead of any address of this host
+    pub bind: Option<IpAddr>,
+    /// If the queries are sent over TCP instead of UDP
+    pub tcp: bool,
+    /// How long to wait for an answer
+    pub timeout: Duration,
+}
+
+impl Default for DirectOptions {
+    fn default() -> Self {
+        DirectOptions {
+            bind: None,
+            tcp: false,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// A query for the records of type `record_type` of `name`, that doesn't ask
+/// the name server to recurse.
+fn request(name: &Name, record_type: RecordType) -> Message {
+    let mut request = Message::new();
+    request
+        .set_id(rand::random())
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(false)
+        .add_query(Query::query(name.clone(), record_type));
+    request
+}
+
+/// The address the queries to `server` are sent from.
+fn local_addr(server: &SocketAddr, options: &DirectOptions) -> SocketAddr {
+    let ip = options.bind.unwrap_or(match server {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    });
+    SocketAddr::new(ip, 0)
+}
+
+/// Checks that `response` is the answer to `request`.
+fn answer_to(request: &Message, response: &[u8]) -> Result<Message, ResolveError> {
+    let response = Message::from_vec(response)?;
+    if response.id() != request.id() || response.message_type() != MessageType::Response {
+        return Err(ResolveErrorKind::Message("the answer doesn't match the query").into());
+    }
+    Ok(response)
+}
+
+fn timed_out(err: std::io::Error) -> ResolveError {
+    match err.kind() {
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+            ResolveErrorKind::Timeout.into()
+        }
+        _ => err.into(),
+    }
+}
+
+/// Sends the query for `name` straight to the name server at `ip` instead of
+/// through a resolver, and returns its answer as it is, for the checks that
+/// need to know things like if the name server is authoritative for it.
+pub fn query(
+    ip: IpAddr,
+    name: &Name,
+    record_type: RecordType,
+    options: &DirectOptions,
+) -> Result<Message, ResolveError> {
+    let server = SocketAddr::new(ip, PORT);
+    let request = request(name, record_type);
+    let bytes = request.to_vec()?;
+    let deadline = Instant::now() + options.timeout;
+
+    if options.tcp {
+        let socket = Socket::new(
+            Domain::for_address(server),
+            Type::STREAM,
+            Some(Protocol::TCP),
+        )?;
+        socket.bind(&local_addr(&server, options).into())?;
+        socket
+            .connect_timeout(&server.into(), options.timeout)
+            .map_err(timed_out)?;
+        let mut stream = TcpStream::from(socket);
+        stream.set_read_timeout(Some(options.timeout))?;
+        stream.set_write_timeout(Some(options.timeout))?;
+        // over TCP each message starts with its length
+        stream
+            .write_all(&(bytes.len() as u16).to_be_bytes())
+            .and_then(|_| stream.write_all(&bytes))
+            .map_err(timed_out)?;
+        let mut len = [0u8; 2];
+        stream.read_exact(&mut len).map_err(timed_out)?;
+        let mut response = vec![0u8; u16::from_be_bytes(len) as usize];
+        stream.read_exact(&mut response).map_err(timed_out)?;
+        return answer_to(&request, &response);
+    }
+
+    let socket = UdpSocket::bind(local_addr(&server, options))?;
+    socket.connect(server)?;
+    socket.send(&bytes)?;
+    let mut buf = [0u8; MAX_UDP_SIZE];
+    // anything else that arrives on the socket is skipped
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(ResolveErrorKind::Timeout.into());
+        }
+        socket.set_read_timeout(Some(left))?;
+        let len = socket.recv(&mut buf).map_err(timed_out)?;
+        if let Ok(response) = answer_to(&request, &buf[..len]) {
+            return Ok(response);
+        }
+    }
+}
+
+/// The asynchronous version of `query`.
+pub async fn query_async(
+    ip: IpAddr,
+    name: &Name,
+    record_type: RecordType,
+    options: &DirectOptions,
+) -> Result<Message, ResolveError> {
+    match tokio::time::timeout(options.timeout, exchange(ip, name, record_type, options)).await {
+        Ok(response) => response,
+        Err(_) => Err(ResolveErrorKind::Timeout.into()),
+    }
+}
+
+async fn exchange(
+    ip: IpAddr,
+    name: &Name,
+    record_type: RecordType,
+    options: &DirectOptions,
+) -> Result<Message, ResolveError> {
+    let server = SocketAddr::new(ip, PORT);
+    let request = request(name, record_type);
+    let bytes = request.to_vec()?;
+
+    if options.tcp {
+        let socket = match server {
+            SocketAddr::V4(_) => tokio::net::TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => tokio::net::TcpSocket::new_v6()?,
+        };
+        socket.bind(local_addr(&server, options))?;
+        let mut stream = socket.connect(server).await?;
+        stream
+            .write_all(&(bytes.len() as u16).to_be_bytes())
+            .await?;
+        stream.write_all(&bytes).await?;
+        let len = stream.read_u16().await?;
+        let mut response = vec![0u8; len as usize];
+        stream.read_exact(&mut response).await?;
+        return answer_to(&request, &response);
+    }
+
+    let socket = tokio::net::UdpSocket::bind(local_addr(&server, options)).await?;
+    socket.connect(server).await?;
+    socket.send(&bytes).await?;
+    let mut buf = [0u8; MAX_UDP_SIZE];
+    loop {
+        let len = socket.recv(&mut buf).await?;
+        if let Ok(response) = answer_to(&request, &buf[..len]) {
+            return Ok(response);
+        }
+    }
+}
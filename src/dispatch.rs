@@ -0,0 +1,263 @@
+use crate::CheckOutcome;
+use futures::{SinkExt, StreamExt};
+use rand::Rng;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Poll, Waker};
+use std::time::Duration;
+use tracing::debug;
+use trust_dns_resolver::proto::op::ResponseCode;
+
+/// How many of the latest answers the SERVFAIL rate is taken over.
+const THROTTLE_WINDOW: usize = 50;
+/// The share of SERVFAIL answers that the adaptive throttle slows down at.
+const THROTTLE_START: f64 = 0.5;
+/// The share of SERVFAIL answers the lookups speed up again at.
+const THROTTLE_STOP: f64 = 0.2;
+const MIN_COOLDOWN: Duration = Duration::from_secs(1);
+const MAX_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// How `dispatch` spreads the checks out over time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DispatchOptions {
+    /// How many checks are in flight at the same time
+    pub concurrency: usize,
+    /// The most checks to start per second
+    pub rate: Option<f64>,
+    /// If the checks slow down while many of them get SERVFAIL answers
+    pub adaptive_throttle: bool,
+    /// How many checks to start before waiting for all of them to complete
+    pub batch_size: Option<usize>,
+    /// How long to wait between two batches
+    pub batch_pause: Option<Duration>,
+    /// How long the checks may run, after which the ones that haven't
+    /// completed are reported as TimedOut
+    pub max_runtime: Option<Duration>,
+}
+
+impl Default for DispatchOptions {
+    fn default() -> Self {
+        DispatchOptions {
+            concurrency: 50,
+            rate: None,
+            adaptive_throttle: false,
+            batch_size: None,
+            batch_pause: None,
+            max_runtime: None,
+        }
+    }
+}
+
+/// Keeps track of the share of SERVFAIL answers. When it rises the dispatch
+/// pauses for a cooldown, that doubles for as long as the rate stays high,
+/// and the checks are spaced out until the rate has dropped.
+struct Throttle {
+    latest: VecDeque<bool>,
+    cooldown: Option<Duration>,
+    paused_until: tokio::time::Instant,
+}
+
+impl Throttle {
+    fn new() -> Throttle {
+        Throttle {
+            latest: VecDeque::with_capacity(THROTTLE_WINDOW),
+            cooldown: None,
+            paused_until: tokio::time::Instant::now(),
+        }
+    }
+
+    fn record(&mut self, servfail: bool) {
+        if self.latest.len() == THROTTLE_WINDOW {
+            self.latest.pop_front();
+        }
+        self.latest.push_back(servfail);
+        if self.latest.len() < THROTTLE_WINDOW / 2 {
+            return;
+        }
+
+        let rate = self.latest.iter().filter(|servfail| **servfail).count() as f64
+            / self.latest.len() as f64;
+        let now = tokio::time::Instant::now();
+        match self.cooldown {
+            None if rate >= THROTTLE_START => {
+                debug!(
+                    servfail_rate = rate,
+                    cooldown = ?MIN_COOLDOWN,
+                    "many SERVFAIL answers, slowing down"
+                );
+                self.cooldown = Some(MIN_COOLDOWN);
+                self.paused_until = now + MIN_COOLDOWN;
+            }
+            Some(cooldown) if rate >= THROTTLE_START && now >= self.paused_until => {
+                let cooldown = (cooldown * 2).min(MAX_COOLDOWN);
+                debug!(
+                    servfail_rate = rate,
+                    ?cooldown,
+                    "still many SERVFAIL answers, slowing down more"
+                );
+                self.cooldown = Some(cooldown);
+                self.paused_until = now + cooldown;
+            }
+            Some(_) if rate <= THROTTLE_STOP => {
+                debug!(
+                    servfail_rate = rate,
+                    "fewer SERVFAIL answers, speeding up again"
+                );
+                self.cooldown = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// When the next check can be dispatched.
+    fn next_dispatch(&self) -> Option<tokio::time::Instant> {
+        let cooldown = self.cooldown?;
+        Some(
+            self.paused_until
+                .max(tokio::time::Instant::now() + cooldown / 10),
+        )
+    }
+}
+
+/// Checks the domains from `input` with `check`, as `options` allows, and
+/// hands each one over to `on_result` with its index in the input as soon as
+/// its check is done. The domains that come with an outcome, like one from a
+/// cache, aren't checked again but handed over as they are.
+///
+/// The input is read on its own thread, so that waiting for more input
+/// doesn't hold up the checks in flight. Once `stop` is set no new checks are
+/// started, and when the time is up the checks in flight and the domains
+/// that haven't been checked yet are handed over as TimedOut. The first error,
+/// from the input or from `on_result`, ends the dispatch.
+pub async fn dispatch<E, F>(
+    input: impl Iterator<Item = Result<(String, Option<CheckOutcome>), E>> + Send + 'static,
+    options: &DispatchOptions,
+    stop: &AtomicBool,
+    check: impl Fn(String) -> F,
+    mut on_result: impl FnMut(usize, String, CheckOutcome) -> Result<(), E>,
+) -> Result<(), E>
+where
+    E: Send + 'static,
+    F: Future<Output = CheckOutcome>,
+{
+    let check = &check;
+    // space out the dispatch of the queries if there is a rate limit, with
+    // some jitter so they don't go out in synchronized bursts
+    let spacing = options.rate.map(|qps| Duration::from_secs_f64(1.0 / qps));
+    let throttle = options
+        .adaptive_throttle
+        .then(|| RefCell::new(Throttle::new()));
+    let throttle = &throttle;
+    // with a batch size the checks are dispatched until a batch is full, and
+    // the next batch waits until all of them have completed
+    let dispatched = &Cell::new(0usize);
+    let completed = &Cell::new(0usize);
+    let batch_waker: &RefCell<Option<Waker>> = &RefCell::new(None);
+    let (mut sender, mut receiver) = futures::channel::mpsc::channel(options.concurrency);
+    std::thread::spawn(move || {
+        for item in input.enumerate() {
+            if futures::executor::block_on(sender.send(item)).is_err() {
+                break;
+            }
+        }
+    });
+
+    // the domains that have been taken from the input but not completed, so
+    // they can be reported as TimedOut if the dispatch runs out of time
+    let in_flight = &RefCell::new(BTreeMap::new());
+    let deadline = options
+        .max_runtime
+        .map(|max_runtime| tokio::time::Instant::now() + max_runtime);
+    {
+        let lookups = receiver
+            .by_ref()
+            .take_while(|_| futures::future::ready(!stop.load(Ordering::SeqCst)))
+            .then(|item| async move {
+                if let (i, Ok((l, None))) = &item {
+                    in_flight.borrow_mut().insert(*i, l.clone());
+                }
+                let cached = matches!(item, (_, Ok((_, Some(_)))));
+                if let (Some(spacing), false) = (spacing, cached) {
+                    tokio::time::sleep(spacing.mul_f64(rand::thread_rng().gen_range(0.5..1.5)))
+                        .await;
+                }
+                let next_dispatch = throttle
+                    .as_ref()
+                    .and_then(|throttle| throttle.borrow().next_dispatch());
+                if let (Some(next_dispatch), false) = (next_dispatch, cached) {
+                    tokio::time::sleep_until(next_dispatch).await;
+                }
+                if let (Some(batch_size), (_, Ok((_, None)))) = (options.batch_size, &item) {
+                    if dispatched.get() > 0 && dispatched.get().is_multiple_of(batch_size) {
+                        futures::future::poll_fn(|cx| {
+                            if completed.get() == dispatched.get() {
+                                Poll::Ready(())
+                            } else {
+                                *batch_waker.borrow_mut() = Some(cx.waker().clone());
+                                Poll::Pending
+                            }
+                        })
+                        .await;
+                        debug!(batch = dispatched.get() / batch_size, "batch done");
+                        if let Some(pause) = options.batch_pause {
+                            tokio::time::sleep(pause).await;
+                        }
+                    }
+                    dispatched.set(dispatched.get() + 1);
+                }
+                item
+            })
+            .map(|(i, l)| async move {
+                let (l, cached) = l?;
+                let outcome = match cached {
+                    Some(outcome) => outcome,
+                    None => {
+                        let outcome = check(l.clone()).await;
+                        if let Some(throttle) = throttle {
+                            throttle
+                                .borrow_mut()
+                                .record(outcome.response_code == Some(ResponseCode::ServFail));
+                        }
+                        completed.set(completed.get() + 1);
+                        if let Some(waker) = batch_waker.borrow_mut().take() {
+                            waker.wake();
+                        }
+                        outcome
+                    }
+                };
+                in_flight.borrow_mut().remove(&i);
+                Ok::<_, E>((i, l, outcome))
+            })
+            .buffer_unordered(options.concurrency);
+        futures::pin_mut!(lookups);
+
+        // do at most `concurrency` of these futures at the same time, and
+        // hand each result over as soon as it's done, until the time is up
+        loop {
+            let lookup = match deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline, lookups.next()).await {
+                    Ok(lookup) => lookup,
+                    Err(_) => break,
+                },
+                None => lookups.next().await,
+            };
+            let Some(lookup) = lookup else {
+                return Ok(());
+            };
+            let (i, l, outcome) = lookup?;
+            on_result(i, l, outcome)?;
+        }
+    }
+
+    for (i, l) in std::mem::take(&mut *in_flight.borrow_mut()) {
+        on_result(i, l, CheckOutcome::timed_out())?;
+    }
+    // and the domains that weren't taken from the input before the time ran out
+    while let Some((i, l)) = receiver.next().await {
+        let (l, cached) = l?;
+        on_result(i, l, cached.unwrap_or_else(CheckOutcome::timed_out))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+/// A hosting provider that can be claimed by a third party if a CNAME keeps
+/// pointing at it after the resource behind it has been deprovisioned.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fingerprint {
+    /// Human readable name of the provider
+    pub service: String,
+    /// Suffix of the CNAME target that identifies the provider
+    pub cname_suffix: String,
+    /// HTTP status the provider responds with for unclaimed resources
+    pub http_status: Option<u16>,
+    /// Part of the body the provider serves for unclaimed resources
+    pub body: Option<String>,
+}
+
+impl Fingerprint {
+    pub fn new(
+        service: &str,
+        cname_suffix: &str,
+        http_status: Option<u16>,
+        body: Option<&str>,
+    ) -> Fingerprint {
+        Fingerprint {
+            service: service.to_string(),
+            cname_suffix: cname_suffix.to_string(),
+            http_status,
+            body: body.map(|b| b.to_string()),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.service.trim().is_empty() {
+            return Err(anyhow!("service can't be empty"));
+        }
+        if self.cname_suffix.trim_end_matches('.').trim().is_empty() {
+            return Err(anyhow!("cname_suffix can't be empty"));
+        }
+        if let Some(status) = self.http_status {
+            if !(100..=599).contains(&status) {
+                return Err(anyhow!("http_status {} is not a valid HTTP status", status));
+            }
+        }
+        if self.http_status.is_some() && self.body.is_none() {
+            return Err(anyhow!("http_status is set but body is missing"));
+        }
+        Ok(())
+    }
+
+    /// Checks if `target` is the suffix itself or a subdomain of it.
+    pub fn matches(&self, target: &str) -> bool {
+        let target = target.trim_end_matches('.').to_ascii_lowercase();
+        let suffix = self.cname_suffix.trim_end_matches('.').to_ascii_lowercase();
+
+        target == suffix || target.ends_with(&format!(".{}", suffix))
+    }
+
+    /// Checks if an HTTP response is the page the provider serves for
+    /// unclaimed resources. Fingerprints without a body never match.
+    pub fn matches_response(&self, status: u16, body: &str) -> bool {
+        match &self.body {
+            Some(signature) => {
+                self.http_status.is_none_or(|s| s == status) && body.contains(signature)
+            }
+            None => false,
+        }
+    }
+}
+
+/// The built-in list of providers known to be vulnerable to takeovers.
+pub fn default_fingerprints() -> Vec<Fingerprint> {
+    vec![
+        Fingerprint::new(
+            "AWS/S3",
+            "s3.amazonaws.com",
+            Some(404),
+            Some("The specified bucket does not exist"),
+        ),
+        Fingerprint::new("AWS/Elastic Beanstalk", "elasticbeanstalk.com", None, None),
+        Fingerprint::new(
+            "GitHub Pages",
+            "github.io",
+            Some(404),
+            Some("There isn't a GitHub Pages site here."),
+        ),
+        Fingerprint::new("Heroku", "herokudns.com", Some(404), Some("No such app")),
+        Fingerprint::new("Heroku", "herokuapp.com", Some(404), Some("No such app")),
+        Fingerprint::new("Microsoft Azure", "azurewebsites.net", None, None),
+        Fingerprint::new("Microsoft Azure", "cloudapp.net", None, None),
+        Fingerprint::new("Microsoft Azure", "trafficmanager.net", None, None),
+        Fingerprint::new("Microsoft Azure", "blob.core.windows.net", None, None),
+        Fingerprint::new(
+            "Shopify",
+            "myshopify.com",
+            Some(404),
+            Some("Sorry, this shop is currently unavailable."),
+        ),
+        Fingerprint::new("Surge.sh", "surge.sh", Some(404), Some("project not found")),
+    ]
+}
+
+/// Loads a list of fingerprints from a file. Files ending in `.toml` are read
+/// as TOML with the entries in a `fingerprints` array, everything else is read
+/// as a JSON array.
+pub fn load_fingerprints(path: &Path) -> Result<Vec<Fingerprint>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read fingerprint file {}", path.display()))?;
+
+    let fingerprints = if path.extension().is_some_and(|ext| ext == "toml") {
+        parse_toml(&content)
+    } else {
+        parse_json(&content)
+    };
+
+    fingerprints.with_context(|| format!("invalid fingerprint file {}", path.display()))
+}
+
+fn parse_json(content: &str) -> Result<Vec<Fingerprint>> {
+    let entries: Vec<serde_json::Value> =
+        serde_json::from_str(content).context("expected a json array of fingerprints")?;
+
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            serde_json::from_value(entry)
+                .map_err(anyhow::Error::from)
+                .and_then(|fp: Fingerprint| fp.validate().map(|_| fp))
+                .with_context(|| format!("malformed fingerprint at index {}", i))
+        })
+        .collect()
+}
+
+fn parse_toml(content: &str) -> Result<Vec<Fingerprint>> {
+    let mut table: toml::Table = toml::from_str(content)?;
+    let entries = match table.remove("fingerprints") {
+        Some(toml::Value::Array(entries)) => entries,
+        _ => return Err(anyhow!("expected a fingerprints array")),
+    };
+
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            entry
+                .try_into()
+                .map_err(anyhow::Error::from)
+                .and_then(|fp: Fingerprint| fp.validate().map(|_| fp))
+                .with_context(|| format!("malformed fingerprint at index {}", i))
+        })
+        .collect()
+}
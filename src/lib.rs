@@ -0,0 +1,1297 @@
+pub mod delegation;
+pub mod direct;
+pub mod dispatch;
+pub mod fingerprints;
+pub mod lookup;
+pub mod psl;
+pub mod replay;
+pub mod single_flight;
+
+use delegation::{
+    lame_name_server, lame_name_server_async, unregistered_ns_domain, unregistered_ns_domain_async,
+    LameNameServer,
+};
+use direct::DirectOptions;
+use fingerprints::{default_fingerprints, Fingerprint};
+use futures::{Stream, StreamExt};
+use lookup::{AsyncDnsLookup, DnsLookup};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+use tracing::{debug, trace};
+use trust_dns_resolver::error::ResolveError;
+use trust_dns_resolver::error::ResolveErrorKind;
+use trust_dns_resolver::lookup::Lookup;
+use trust_dns_resolver::lookup_ip::LookupIp;
+use trust_dns_resolver::proto::error::ProtoErrorKind;
+use trust_dns_resolver::proto::op::ResponseCode;
+use trust_dns_resolver::proto::rr::{RData, Record, RecordType};
+
+/// How many CNAME records to follow by default before reporting a chain as too long.
+pub const MAX_CNAME_DEPTH: usize = 10;
+
+/// How long to wait before the first retry of a lookup that failed with an
+/// io error, each further retry waits twice as long.
+const IO_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The longest wait between two retries after io errors.
+const MAX_IO_RETRY_BACKOFF: Duration = Duration::from_secs(10);
+
+/// How long to wait for a provider to answer an HTTP probe.
+const HTTP_PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The outcome of checking a single domain.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LookupResult {
+    Safe,
+    MaybeVulnerable,
+    LookupError,
+    /// The input isn't a valid domain, so it was never looked up
+    InvalidInput,
+    /// The scan ran out of time before the lookup completed
+    TimedOut,
+    /// The name servers that were compared disagree about the domain, like
+    /// one answering with NXDOMAIN and another with an address
+    Inconsistent,
+}
+
+impl Display for LookupResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            LookupResult::Safe => write!(f, "Safe"),
+            LookupResult::MaybeVulnerable => write!(f, "MaybeVulnerable"),
+            LookupResult::LookupError => write!(f, "LookupError"),
+            LookupResult::InvalidInput => write!(f, "InvalidInput"),
+            LookupResult::TimedOut => write!(f, "TimedOut"),
+            LookupResult::Inconsistent => write!(f, "Inconsistent"),
+        }
+    }
+}
+
+/// Why a lookup failed, either without getting an answer from the name
+/// servers or with a CNAME chain that can't be followed to its end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// None of the name servers answered in time
+    Timeout,
+    /// The queries couldn't be sent or the answers couldn't be read
+    Io,
+    /// An answer couldn't be parsed, or didn't follow the protocol
+    Proto,
+    /// There were no name servers to send the query to
+    NoConnections,
+    /// Any other error of the resolver
+    Other,
+    /// The CNAME chain of the domain leads back to a name that's already in it
+    CnameLoop,
+    /// The CNAME chain of the domain is longer than the maximum depth
+    CnameTooLong,
+}
+
+impl ErrorKind {
+    /// The kind of the error a lookup failed with, None if it got an answer,
+    /// even one without records.
+    pub fn of<T>(lookup_result: &Result<T, ResolveError>) -> Option<ErrorKind> {
+        match lookup_result {
+            Ok(_) => None,
+            Err(err) => match err.kind() {
+                ResolveErrorKind::NoRecordsFound { .. } => None,
+                ResolveErrorKind::Timeout => Some(ErrorKind::Timeout),
+                ResolveErrorKind::Io(_) => Some(ErrorKind::Io),
+                // connections that fail are reported by the protocol layer
+                ResolveErrorKind::Proto(err) if matches!(err.kind(), ProtoErrorKind::Io(_)) => {
+                    Some(ErrorKind::Io)
+                }
+                ResolveErrorKind::Proto(_) => Some(ErrorKind::Proto),
+                ResolveErrorKind::NoConnections => Some(ErrorKind::NoConnections),
+                _ => Some(ErrorKind::Other),
+            },
+        }
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            ErrorKind::Timeout => write!(f, "timeout"),
+            ErrorKind::Io => write!(f, "io error"),
+            ErrorKind::Proto => write!(f, "protocol error"),
+            ErrorKind::NoConnections => write!(f, "no name servers"),
+            ErrorKind::Other => write!(f, "resolver error"),
+            ErrorKind::CnameLoop => write!(f, "CNAME loop"),
+            ErrorKind::CnameTooLong => write!(f, "CNAME chain too long"),
+        }
+    }
+}
+
+/// How likely a MaybeVulnerable finding is to be exploitable, based on the check that reported it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Severity {
+    /// The domain only got an answer with a response code like SERVFAIL,
+    /// which name servers also give for problems that go away
+    Low,
+    /// The domain is delegated to a lame name server, or has a CNAME chain
+    /// that ends at a name that doesn't exist outside of a known provider
+    Medium,
+    /// The domain has a dangling CNAME to a known provider, or a name server
+    /// in a domain that anyone can register
+    High,
+}
+
+impl Severity {
+    /// The severity of `outcome`, from the details the checks left in it, or
+    /// None if it isn't MaybeVulnerable.
+    pub fn of(outcome: &CheckOutcome) -> Option<Severity> {
+        if outcome.result != LookupResult::MaybeVulnerable {
+            None
+        } else if outcome.unregistered_ns_domain.is_some() || outcome.provider.is_some() {
+            Some(Severity::High)
+        } else if outcome.dangling_cname.is_some() || outcome.lame_name_server.is_some() {
+            Some(Severity::Medium)
+        } else {
+            Some(Severity::Low)
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Severity::Low => write!(f, "Low"),
+            Severity::Medium => write!(f, "Medium"),
+            Severity::High => write!(f, "High"),
+        }
+    }
+}
+
+/// The outcome of checking a domain, together with the details that led to it.
+///
+/// `check_domain` only does the SERVFAIL check, so it fills in the details of
+/// the answer itself: the response code, error kind, addresses and duration.
+/// The details of the other checks, like `provider`, `dangling_cname`,
+/// `cname_chain`, `lame_name_server` and `unregistered_ns_domain`, are only
+/// filled in by `inspect_domain` and `inspect_domain_async`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckOutcome {
+    pub result: LookupResult,
+    /// How severe the finding is, for the domains that might be vulnerable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+    /// The response code of the answer, if the lookup got one without any records in it
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_response_code",
+        deserialize_with = "deserialize_response_code"
+    )]
+    pub response_code: Option<ResponseCode>,
+    /// Why the lookup failed, if it didn't get an answer at all
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<ErrorKind>,
+    /// The addresses the domain resolved to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ips: Vec<IpAddr>,
+    /// The answers to the A and AAAA queries, when they are looked up separately
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip_versions: Option<IpVersions>,
+    /// The provider a dangling CNAME points to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// The name at the end of the domain's CNAME chain, if it doesn't exist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dangling_cname: Option<String>,
+    /// The names the CNAME records of the domain lead to, in order, when they
+    /// are asked for or when the chain loops or is too long
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cname_chain: Vec<String>,
+    /// The name server the domain is delegated to that doesn't answer for it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lame_name_server: Option<LameNameServer>,
+    /// The domain of a name server the domain is delegated to, if it isn't registered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unregistered_ns_domain: Option<String>,
+    /// The answer of each name server, when several of them are compared
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub answers: Vec<ResolverAnswer>,
+    /// How many milliseconds the lookup of the domain took, on the last attempt if it was retried
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// If the domain was reported as Safe because it's a known exception,
+    /// although the checks found it might be vulnerable
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub suppressed: bool,
+}
+
+impl CheckOutcome {
+    fn invalid_input() -> CheckOutcome {
+        CheckOutcome::without_lookup(LookupResult::InvalidInput)
+    }
+
+    /// The outcome for a domain whose lookup was given up on.
+    pub fn timed_out() -> CheckOutcome {
+        CheckOutcome::without_lookup(LookupResult::TimedOut)
+    }
+
+    /// The outcome for a domain that couldn't be looked up at all.
+    pub fn lookup_error(error_kind: ErrorKind) -> CheckOutcome {
+        CheckOutcome {
+            error_kind: Some(error_kind),
+            ..CheckOutcome::without_lookup(LookupResult::LookupError)
+        }
+    }
+
+    fn without_lookup(result: LookupResult) -> CheckOutcome {
+        CheckOutcome {
+            result,
+            severity: None,
+            response_code: None,
+            error_kind: None,
+            ips: vec![],
+            ip_versions: None,
+            provider: None,
+            dangling_cname: None,
+            cname_chain: vec![],
+            lame_name_server: None,
+            unregistered_ns_domain: None,
+            answers: vec![],
+            duration_ms: None,
+            suppressed: false,
+        }
+    }
+
+    fn from_lookup(
+        lookup_result: &Result<LookupIp, ResolveError>,
+        treat_as_vulnerable: &[ResponseCode],
+        duration: Duration,
+    ) -> CheckOutcome {
+        CheckOutcome {
+            result: is_vulnerable_with(lookup_result, treat_as_vulnerable),
+            severity: None,
+            response_code: response_code(lookup_result),
+            error_kind: ErrorKind::of(lookup_result),
+            ips: match lookup_result {
+                Ok(lookup) => lookup.iter().collect(),
+                Err(_) => vec![],
+            },
+            ip_versions: None,
+            provider: None,
+            dangling_cname: None,
+            cname_chain: vec![],
+            lame_name_server: None,
+            unregistered_ns_domain: None,
+            answers: vec![],
+            duration_ms: Some(duration.as_millis() as u64),
+            suppressed: false,
+        }
+    }
+}
+
+impl Display for CheckOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.result)?;
+        if self.suppressed {
+            write!(f, " (suppressed)")?;
+        }
+        if let Some(severity) = self.severity {
+            write!(f, " (severity {})", severity)?;
+        }
+        match (
+            self.response_code,
+            self.provider.as_ref().or(self.dangling_cname.as_ref()),
+        ) {
+            (Some(code), Some(provider)) => {
+                write!(f, " ({:?}, dangling CNAME to {})", code, provider)
+            }
+            (Some(code), None) => write!(f, " ({:?})", code),
+            (None, Some(provider)) => write!(f, " (dangling CNAME to {})", provider),
+            (None, None) => Ok(()),
+        }?;
+        match self.error_kind {
+            Some(error_kind @ (ErrorKind::CnameLoop | ErrorKind::CnameTooLong)) => write!(
+                f,
+                " ({} through {})",
+                error_kind,
+                self.cname_chain.join(" -> ")
+            )?,
+            Some(error_kind) => write!(f, " ({})", error_kind)?,
+            None => {}
+        }
+        if let Some(versions) = &self.ip_versions {
+            write!(f, " (A {}, AAAA {})", versions.a, versions.aaaa)?;
+        }
+        if let Some(lame) = &self.lame_name_server {
+            write!(f, " ({})", lame)?;
+        }
+        if let Some(ns_domain) = &self.unregistered_ns_domain {
+            write!(f, " (name server domain {} isn't registered)", ns_domain)?;
+        }
+        if self.result == LookupResult::Inconsistent {
+            let answers: Vec<String> = self.answers.iter().map(|a| a.to_string()).collect();
+            write!(f, " ({})", answers.join(", "))?;
+        }
+        if !self.ips.is_empty() {
+            let ips: Vec<String> = self.ips.iter().map(|ip| ip.to_string()).collect();
+            write!(f, " [{}]", ips.join(", "))?;
+        }
+        if let Some(duration_ms) = self.duration_ms {
+            write!(f, " ({}ms)", duration_ms)?;
+        }
+        Ok(())
+    }
+}
+
+/// The answers to the A and AAAA queries of a domain, when the IP versions are looked up separately.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IpVersions {
+    pub a: IpVersion,
+    pub aaaa: IpVersion,
+}
+
+impl IpVersions {
+    fn new(
+        a: &Result<LookupIp, ResolveError>,
+        aaaa: &Result<LookupIp, ResolveError>,
+        treat_as_vulnerable: &[ResponseCode],
+    ) -> IpVersions {
+        IpVersions {
+            a: IpVersion::new(a, treat_as_vulnerable),
+            aaaa: IpVersion::new(aaaa, treat_as_vulnerable),
+        }
+    }
+}
+
+/// The answer to the query for the addresses of one IP version.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IpVersion {
+    /// What the answer would be reported as on its own
+    pub result: LookupResult,
+    /// If the answer had any addresses in it
+    pub present: bool,
+    /// The response code of the answer, if it didn't have any records in it
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_response_code",
+        deserialize_with = "deserialize_response_code"
+    )]
+    pub response_code: Option<ResponseCode>,
+    /// Why the query failed, if it didn't get an answer at all
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<ErrorKind>,
+}
+
+impl IpVersion {
+    fn new(
+        lookup: &Result<LookupIp, ResolveError>,
+        treat_as_vulnerable: &[ResponseCode],
+    ) -> IpVersion {
+        IpVersion {
+            result: is_vulnerable_with(lookup, treat_as_vulnerable),
+            present: lookup
+                .as_ref()
+                .is_ok_and(|lookup| lookup.iter().next().is_some()),
+            response_code: response_code(lookup),
+            error_kind: ErrorKind::of(lookup),
+        }
+    }
+}
+
+impl Display for IpVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match (self.present, self.response_code, self.error_kind) {
+            (true, _, _) => write!(f, "present"),
+            (false, _, Some(error_kind)) => write!(f, "{}", error_kind),
+            (false, Some(code), None) if code != ResponseCode::NoError => write!(f, "{:?}", code),
+            (false, _, None) => write!(f, "missing"),
+        }
+    }
+}
+
+/// What one of the compared name servers answered for a domain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResolverAnswer {
+    /// The address of the name server
+    pub name_server: String,
+    /// What the domain was reported as with this name server alone
+    pub result: LookupResult,
+    /// The response code of the answer, if it didn't have any records in it
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_response_code",
+        deserialize_with = "deserialize_response_code"
+    )]
+    pub response_code: Option<ResponseCode>,
+    /// Why the lookup failed, if it didn't get an answer at all
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<ErrorKind>,
+    /// The addresses the domain resolved to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ips: Vec<IpAddr>,
+}
+
+impl ResolverAnswer {
+    fn new(name_server: String, outcome: &CheckOutcome) -> ResolverAnswer {
+        ResolverAnswer {
+            name_server,
+            result: outcome.result,
+            response_code: outcome.response_code,
+            error_kind: outcome.error_kind,
+            ips: outcome.ips.clone(),
+        }
+    }
+}
+
+impl Display for ResolverAnswer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}: ", self.name_server)?;
+        match (self.ips.is_empty(), self.response_code, self.error_kind) {
+            (false, _, _) => {
+                let ips: Vec<String> = self.ips.iter().map(|ip| ip.to_string()).collect();
+                write!(f, "{}", ips.join(" "))
+            }
+            (true, _, Some(error_kind)) => write!(f, "{}", error_kind),
+            (true, Some(code), None) => write!(f, "{:?}", code),
+            (true, None, None) => write!(f, "{}", self.result),
+        }
+    }
+}
+
+/// Combines the outcomes of checking a domain with each of several name
+/// servers, given with the name of the server. They disagree when some of
+/// them answer with addresses and others without, or with different
+/// response codes, which is reported as Inconsistent. The name servers whose
+/// lookup failed aren't compared, and a domain that might be vulnerable with
+/// any of them is reported as that. Otherwise the outcome is the first one.
+/// Either way the outcome has the answer of every name server in it.
+pub fn compare_answers(mut outcomes: Vec<(String, CheckOutcome)>) -> CheckOutcome {
+    let answers: Vec<ResolverAnswer> = outcomes
+        .iter()
+        .map(|(name_server, outcome)| ResolverAnswer::new(name_server.clone(), outcome))
+        .collect();
+    let mut kinds = answers
+        .iter()
+        .filter(|answer| answer.error_kind.is_none())
+        .map(|answer| (answer.ips.is_empty(), answer.response_code));
+    let inconsistent = match kinds.next() {
+        Some(first) => kinds.any(|kind| kind != first),
+        None => false,
+    };
+
+    let vulnerable = outcomes
+        .iter()
+        .position(|(_, outcome)| outcome.result == LookupResult::MaybeVulnerable);
+    let mut outcome = match vulnerable {
+        Some(i) => outcomes.swap_remove(i).1,
+        None if inconsistent => CheckOutcome {
+            duration_ms: outcomes.iter().filter_map(|(_, o)| o.duration_ms).max(),
+            ..CheckOutcome::without_lookup(LookupResult::Inconsistent)
+        },
+        None => match outcomes.into_iter().next() {
+            Some((_, outcome)) => outcome,
+            None => CheckOutcome::lookup_error(ErrorKind::NoConnections),
+        },
+    };
+    outcome.answers = answers;
+    outcome
+}
+
+/// Writes the response code with the same name as the text output uses, e.g. `ServFail`.
+pub(crate) fn serialize_response_code<S: Serializer>(
+    code: &Option<ResponseCode>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match code {
+        Some(code) => serializer.collect_str(&format_args!("{:?}", code)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Reads a response code written by `serialize_response_code`.
+pub(crate) fn deserialize_response_code<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<ResponseCode>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(name) => response_code_by_name(&name)
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown response code {:?}", name))),
+        None => Ok(None),
+    }
+}
+
+/// The response code with the given name, like ServFail, ignoring case.
+pub fn response_code_by_name(name: &str) -> Option<ResponseCode> {
+    (0..4096u16)
+        .map(<ResponseCode as From<u16>>::from)
+        .filter(|code| !matches!(code, ResponseCode::Unknown(_)))
+        .find(|code| format!("{:?}", code).eq_ignore_ascii_case(name))
+}
+
+/// Brings a domain from the input to the form it's looked up and reported
+/// as: without surrounding whitespace or a trailing dot, and in lowercase.
+pub fn normalize_domain(domain: &str) -> String {
+    let domain = domain.trim();
+    domain.strip_suffix('.').unwrap_or(domain).to_lowercase()
+}
+
+/// Checks that `domain` is a plausible DNS name: at most 253 characters, made
+/// up of labels of 1 to 63 letters, digits, hyphens or underscores, that
+/// don't start or end with a hyphen.
+pub fn is_valid_domain(domain: &str) -> bool {
+    let domain = domain.strip_suffix('.').unwrap_or(domain);
+    !domain.is_empty()
+        && domain.len() <= 253
+        && domain.split('.').all(|label| {
+            (1..=63).contains(&label.len())
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        })
+}
+
+/// The form of `domain` that is sent to the name servers, with international
+/// labels encoded as punycode, or None if it isn't a valid domain. ASCII
+/// domains are returned as they are.
+pub fn ascii_domain(domain: &str) -> Option<String> {
+    let ascii = if domain.is_ascii() {
+        domain.to_string()
+    } else {
+        idna::domain_to_ascii(domain).ok()?
+    };
+    if is_valid_domain(&ascii) {
+        Some(ascii)
+    } else {
+        None
+    }
+}
+
+/// Maps the result of an ip lookup to a `LookupResult`. A SERVFAIL response
+/// indicates that the domain might be vulnerable to a name server takeover.
+/// Lookups that time out are treated as Safe.
+pub fn is_vulnerable(lookup_result: &Result<LookupIp, ResolveError>) -> LookupResult {
+    is_vulnerable_with(lookup_result, &[])
+}
+
+/// The same as `is_vulnerable`, but answers with any of the response codes in
+/// `treat_as_vulnerable` are reported as MaybeVulnerable as well.
+pub fn is_vulnerable_with(
+    lookup_result: &Result<LookupIp, ResolveError>,
+    treat_as_vulnerable: &[ResponseCode],
+) -> LookupResult {
+    match lookup_result {
+        Ok(_) => LookupResult::Safe,
+        Err(err) => match err.kind() {
+            ResolveErrorKind::NoRecordsFound { response_code, .. }
+                if *response_code == ResponseCode::ServFail
+                    || treat_as_vulnerable.contains(response_code) =>
+            {
+                LookupResult::MaybeVulnerable
+            }
+            ResolveErrorKind::Io(_) => LookupResult::LookupError,
+            ResolveErrorKind::Proto(_) => LookupResult::LookupError,
+            _ => LookupResult::Safe,
+        },
+    }
+}
+
+/// The response code of a lookup that got an answer without any records in it.
+pub fn response_code(lookup_result: &Result<LookupIp, ResolveError>) -> Option<ResponseCode> {
+    match lookup_result {
+        Ok(_) => None,
+        Err(err) => match err.kind() {
+            ResolveErrorKind::NoRecordsFound { response_code, .. } => Some(*response_code),
+            _ => None,
+        },
+    }
+}
+
+/// Looks up the addresses of `domain` with the given resolver and reports if
+/// it might be vulnerable because of a SERVFAIL answer, together with the
+/// details of the answer, like its response code and addresses and how long
+/// it took. `inspect_domain` does the CNAME and delegation checks as well, and
+/// is the only one that reports the CNAME chain and provider.
+pub fn check_domain(domain: &str, resolver: &impl DnsLookup) -> CheckOutcome {
+    match ascii_domain(domain) {
+        Some(domain) => {
+            let start = Instant::now();
+            let lookup = resolver.lookup_ip(&domain);
+            with_severity(CheckOutcome::from_lookup(&lookup, &[], start.elapsed()))
+        }
+        None => CheckOutcome::invalid_input(),
+    }
+}
+
+/// Settings for the checks `inspect_domain` performs on top of the SERVFAIL check.
+#[derive(Debug, Clone)]
+pub struct InspectOptions {
+    /// Providers to look for at the end of CNAME chains
+    pub fingerprints: Vec<Fingerprint>,
+    /// If CNAMEs to a provider that still resolve should be confirmed with an HTTP request
+    pub http_probe: bool,
+    /// Response codes that are reported as MaybeVulnerable, on top of SERVFAIL
+    pub treat_as_vulnerable: Vec<ResponseCode>,
+    /// The record type to query instead of the A and AAAA records
+    pub record_type: Option<RecordType>,
+    /// Query the name servers of the domains directly with these options, to look for lame delegations
+    pub lame_delegation: Option<DirectOptions>,
+    /// If the domains of the name servers should be checked for being registered
+    pub unregistered_ns: bool,
+    /// How many times to look up a domain again when it gets a SERVFAIL answer, before it's reported
+    pub servfail_retries: usize,
+    /// How many times to look up a domain again when the lookup fails with an
+    /// io error, waiting twice as long before each retry
+    pub io_retries: usize,
+    /// If the CNAME chain of every domain should be followed and kept in the outcome
+    pub cname_chain: bool,
+    /// How many CNAME records to follow before a chain is reported as too long
+    pub max_cname_depth: usize,
+    /// If the A and AAAA records should be queried separately and reported for each IP version
+    pub ip_versions: bool,
+}
+
+impl Default for InspectOptions {
+    fn default() -> Self {
+        InspectOptions {
+            fingerprints: default_fingerprints(),
+            http_probe: false,
+            treat_as_vulnerable: vec![],
+            record_type: None,
+            lame_delegation: None,
+            unregistered_ns: false,
+            servfail_retries: 0,
+            io_retries: 2,
+            cname_chain: false,
+            max_cname_depth: MAX_CNAME_DEPTH,
+            ip_versions: false,
+        }
+    }
+}
+
+/// Looks up `domain` and reports if it might be vulnerable, either because of
+/// a SERVFAIL response or because it has a dangling CNAME pointing at one of
+/// the providers in the fingerprint list.
+pub fn inspect_domain(
+    domain: &str,
+    resolver: &impl DnsLookup,
+    options: &InspectOptions,
+) -> CheckOutcome {
+    let outcome = with_severity(inspect(domain, resolver, options));
+    match ascii_domain(domain) {
+        Some(domain) if options.cname_chain && outcome.cname_chain.is_empty() => {
+            let (chain, broken) = follow_cnames(&domain, resolver, options.max_cname_depth);
+            with_chain(&domain, outcome, chain, broken)
+        }
+        _ => outcome,
+    }
+}
+
+/// The asynchronous version of `inspect_domain`.
+pub async fn inspect_domain_async(
+    domain: &str,
+    resolver: &impl AsyncDnsLookup,
+    options: &InspectOptions,
+) -> CheckOutcome {
+    let outcome = with_severity(inspect_async(domain, resolver, options).await);
+    match ascii_domain(domain) {
+        Some(domain) if options.cname_chain && outcome.cname_chain.is_empty() => {
+            let (chain, broken) =
+                follow_cnames_async(&domain, resolver, options.max_cname_depth).await;
+            with_chain(&domain, outcome, chain, broken)
+        }
+        _ => outcome,
+    }
+}
+
+/// Checks the domains from `domains` with `inspect_domain_async` as they come
+/// in, with at most `concurrency` checks in flight at the same time, and
+/// yields each domain with its outcome as soon as its check is done. The
+/// outcomes come in the order the checks complete, not the order of the
+/// domains, and nothing is buffered apart from the checks in flight.
+pub fn check_stream<'a>(
+    domains: impl Stream<Item = String> + 'a,
+    resolver: &'a impl AsyncDnsLookup,
+    options: &'a InspectOptions,
+    concurrency: NonZeroUsize,
+) -> impl Stream<Item = (String, CheckOutcome)> + 'a {
+    domains
+        .map(move |domain| async move {
+            let outcome = inspect_domain_async(&domain, resolver, options).await;
+            (domain, outcome)
+        })
+        .buffer_unordered(concurrency.get())
+}
+
+/// Keeps the CNAME chain that was asked for in the outcome, and reports a
+/// chain that can't be followed if nothing else was found.
+fn with_chain(
+    domain: &str,
+    outcome: CheckOutcome,
+    chain: Vec<String>,
+    broken: Option<ErrorKind>,
+) -> CheckOutcome {
+    match broken {
+        Some(error_kind) if outcome.result == LookupResult::Safe => {
+            broken_chain(domain, outcome, chain, error_kind)
+        }
+        _ => CheckOutcome {
+            cname_chain: chain_targets(chain),
+            ..outcome
+        },
+    }
+}
+
+/// Reports a domain whose CNAME chain loops or is too long as a LookupError,
+/// with the chain as far as it was followed.
+fn broken_chain(
+    domain: &str,
+    outcome: CheckOutcome,
+    chain: Vec<String>,
+    error_kind: ErrorKind,
+) -> CheckOutcome {
+    debug!(domain, ?chain, %error_kind, "CNAME chain can't be followed");
+    CheckOutcome {
+        result: LookupResult::LookupError,
+        error_kind: Some(error_kind),
+        cname_chain: chain_targets(chain),
+        ..outcome
+    }
+}
+
+fn with_severity(outcome: CheckOutcome) -> CheckOutcome {
+    CheckOutcome {
+        severity: Severity::of(&outcome),
+        ..outcome
+    }
+}
+
+fn inspect(domain: &str, resolver: &impl DnsLookup, options: &InspectOptions) -> CheckOutcome {
+    let domain = match ascii_domain(domain) {
+        Some(domain) => domain,
+        None => {
+            debug!(domain, "not a valid domain, skipping the lookup");
+            return CheckOutcome::invalid_input();
+        }
+    };
+
+    // the duration is of the last attempt, without the retries and their backoff
+    let mut start = Instant::now();
+    let (mut lookup, mut versions) = lookup_records(&domain, resolver, options);
+    let mut retries = Retries::new(options);
+    while let Some(backoff) = retries.next(&domain, &lookup) {
+        std::thread::sleep(backoff);
+        start = Instant::now();
+        (lookup, versions) = lookup_records(&domain, resolver, options);
+    }
+    let outcome = CheckOutcome {
+        ip_versions: versions,
+        ..CheckOutcome::from_lookup(&lookup, &options.treat_as_vulnerable, start.elapsed())
+    };
+    log_lookup(&domain, &lookup, &outcome);
+    if let Some(opts) = options
+        .lame_delegation
+        .as_ref()
+        .filter(|_| outcome.result == LookupResult::Safe)
+    {
+        if let Some(lame) = lame_name_server(&domain, resolver, opts) {
+            return CheckOutcome {
+                result: LookupResult::MaybeVulnerable,
+                lame_name_server: Some(lame),
+                ..outcome
+            };
+        }
+    }
+    if options.unregistered_ns && outcome.result == LookupResult::Safe {
+        if let Some(ns_domain) = unregistered_ns_domain(&domain, resolver) {
+            return CheckOutcome {
+                result: LookupResult::MaybeVulnerable,
+                unregistered_ns_domain: Some(ns_domain),
+                ..outcome
+            };
+        }
+    }
+    let unresolved = is_unresolved(&lookup);
+    if outcome.result != LookupResult::Safe || !(unresolved || options.http_probe) {
+        return outcome;
+    }
+
+    let (chain, broken) = follow_cnames(&domain, resolver, options.max_cname_depth);
+    if let Some(error_kind) = broken {
+        return broken_chain(&domain, outcome, chain, error_kind);
+    }
+
+    if chain.len() < 2 {
+        return outcome;
+    }
+    let target = &chain[chain.len() - 1];
+    let fp = matching_provider(&chain, &options.fingerprints);
+    if let Some(fp) = fp {
+        debug!(
+            domain,
+            provider = fp.service,
+            "CNAME chain ends at a known provider"
+        );
+    }
+
+    if unresolved {
+        dangling(
+            &domain,
+            outcome,
+            target,
+            fp,
+            &resolver.lookup_ip(target.as_str()),
+        )
+    } else {
+        match fp {
+            Some(fp) if http_probe(&domain, fp) => CheckOutcome {
+                result: LookupResult::MaybeVulnerable,
+                provider: Some(fp.service.clone()),
+                ..outcome
+            },
+            _ => outcome,
+        }
+    }
+}
+
+async fn inspect_async(
+    domain: &str,
+    resolver: &impl AsyncDnsLookup,
+    options: &InspectOptions,
+) -> CheckOutcome {
+    let domain = match ascii_domain(domain) {
+        Some(domain) => domain,
+        None => {
+            debug!(domain, "not a valid domain, skipping the lookup");
+            return CheckOutcome::invalid_input();
+        }
+    };
+
+    let mut start = Instant::now();
+    let (mut lookup, mut versions) = lookup_records_async(&domain, resolver, options).await;
+    let mut retries = Retries::new(options);
+    while let Some(backoff) = retries.next(&domain, &lookup) {
+        tokio::time::sleep(backoff).await;
+        start = Instant::now();
+        (lookup, versions) = lookup_records_async(&domain, resolver, options).await;
+    }
+    let outcome = CheckOutcome {
+        ip_versions: versions,
+        ..CheckOutcome::from_lookup(&lookup, &options.treat_as_vulnerable, start.elapsed())
+    };
+    log_lookup(&domain, &lookup, &outcome);
+    if let Some(opts) = options
+        .lame_delegation
+        .as_ref()
+        .filter(|_| outcome.result == LookupResult::Safe)
+    {
+        if let Some(lame) = lame_name_server_async(&domain, resolver, opts).await {
+            return CheckOutcome {
+                result: LookupResult::MaybeVulnerable,
+                lame_name_server: Some(lame),
+                ..outcome
+            };
+        }
+    }
+    if options.unregistered_ns && outcome.result == LookupResult::Safe {
+        if let Some(ns_domain) = unregistered_ns_domain_async(&domain, resolver).await {
+            return CheckOutcome {
+                result: LookupResult::MaybeVulnerable,
+                unregistered_ns_domain: Some(ns_domain),
+                ..outcome
+            };
+        }
+    }
+    let unresolved = is_unresolved(&lookup);
+    if outcome.result != LookupResult::Safe || !(unresolved || options.http_probe) {
+        return outcome;
+    }
+
+    let (chain, broken) = follow_cnames_async(&domain, resolver, options.max_cname_depth).await;
+    if let Some(error_kind) = broken {
+        return broken_chain(&domain, outcome, chain, error_kind);
+    }
+
+    if chain.len() < 2 {
+        return outcome;
+    }
+    let target = &chain[chain.len() - 1];
+    let fp = matching_provider(&chain, &options.fingerprints);
+    if let Some(fp) = fp {
+        debug!(
+            domain,
+            provider = fp.service,
+            "CNAME chain ends at a known provider"
+        );
+    }
+
+    if unresolved {
+        let target_lookup = resolver.lookup_ip(target.as_str()).await;
+        dangling(&domain, outcome, target, fp, &target_lookup)
+    } else {
+        match fp {
+            Some(fp) if http_probe_async(&domain, fp).await => CheckOutcome {
+                result: LookupResult::MaybeVulnerable,
+                provider: Some(fp.service.clone()),
+                ..outcome
+            },
+            _ => outcome,
+        }
+    }
+}
+
+/// The retries of the lookup of a domain, after SERVFAIL answers and io errors.
+struct Retries<'a> {
+    options: &'a InspectOptions,
+    servfail: usize,
+    io: usize,
+}
+
+impl<'a> Retries<'a> {
+    fn new(options: &'a InspectOptions) -> Retries<'a> {
+        Retries {
+            options,
+            servfail: 0,
+            io: 0,
+        }
+    }
+
+    /// How long to wait before looking up the domain again after `lookup`,
+    /// or None if it isn't retried.
+    fn next(&mut self, domain: &str, lookup: &Result<LookupIp, ResolveError>) -> Option<Duration> {
+        if response_code(lookup) == Some(ResponseCode::ServFail)
+            && self.servfail < self.options.servfail_retries
+        {
+            self.servfail += 1;
+            debug!(
+                domain,
+                retry = self.servfail,
+                "looking up again after SERVFAIL"
+            );
+            Some(Duration::ZERO)
+        } else if ErrorKind::of(lookup) == Some(ErrorKind::Io) && self.io < self.options.io_retries
+        {
+            let backoff = IO_RETRY_BACKOFF
+                .saturating_mul(2u32.saturating_pow(self.io as u32))
+                .min(MAX_IO_RETRY_BACKOFF);
+            self.io += 1;
+            debug!(
+                domain,
+                retry = self.io,
+                ?backoff,
+                "looking up again after an io error"
+            );
+            Some(backoff)
+        } else {
+            None
+        }
+    }
+}
+
+/// Reports a domain whose CNAME chain ends at `target` as MaybeVulnerable if
+/// the target doesn't exist, or if it has no records and belongs to a known
+/// provider.
+fn dangling(
+    domain: &str,
+    outcome: CheckOutcome,
+    target: &str,
+    fp: Option<&Fingerprint>,
+    target_lookup: &Result<LookupIp, ResolveError>,
+) -> CheckOutcome {
+    let nxdomain = response_code(target_lookup) == Some(ResponseCode::NXDomain);
+    if !(nxdomain || fp.is_some() && is_unresolved(target_lookup)) {
+        return outcome;
+    }
+
+    let target = target.strip_suffix('.').unwrap_or(target);
+    debug!(domain, target, "CNAME chain ends at a name without records");
+    CheckOutcome {
+        result: LookupResult::MaybeVulnerable,
+        provider: fp.map(|fp| fp.service.clone()),
+        dangling_cname: Some(target.to_string()),
+        ..outcome
+    }
+}
+
+/// Looks up the records of `domain` that are checked, the A and AAAA records
+/// unless another record type is set in `options`. When the IP versions are
+/// looked up separately, their answers are returned as well.
+fn lookup_records(
+    domain: &str,
+    resolver: &impl DnsLookup,
+    options: &InspectOptions,
+) -> (Result<LookupIp, ResolveError>, Option<IpVersions>) {
+    match options.record_type {
+        Some(record_type) => {
+            debug!(domain, %record_type, "looking up records");
+            (
+                resolver.lookup(domain, record_type).map(LookupIp::from),
+                None,
+            )
+        }
+        None if options.ip_versions => {
+            debug!(domain, "looking up A and AAAA records separately");
+            let a = resolver.lookup(domain, RecordType::A).map(LookupIp::from);
+            let aaaa = resolver
+                .lookup(domain, RecordType::AAAA)
+                .map(LookupIp::from);
+            by_ip_version(a, aaaa, options)
+        }
+        None => {
+            debug!(domain, "looking up A and AAAA records");
+            (resolver.lookup_ip(domain), None)
+        }
+    }
+}
+
+/// The asynchronous version of `lookup_records`.
+async fn lookup_records_async(
+    domain: &str,
+    resolver: &impl AsyncDnsLookup,
+    options: &InspectOptions,
+) -> (Result<LookupIp, ResolveError>, Option<IpVersions>) {
+    match options.record_type {
+        Some(record_type) => {
+            debug!(domain, %record_type, "looking up records");
+            let lookup = resolver.lookup(domain, record_type).await;
+            (lookup.map(LookupIp::from), None)
+        }
+        None if options.ip_versions => {
+            debug!(domain, "looking up A and AAAA records separately");
+            let a = resolver
+                .lookup(domain, RecordType::A)
+                .await
+                .map(LookupIp::from);
+            let aaaa = resolver
+                .lookup(domain, RecordType::AAAA)
+                .await
+                .map(LookupIp::from);
+            by_ip_version(a, aaaa, options)
+        }
+        None => {
+            debug!(domain, "looking up A and AAAA records");
+            (resolver.lookup_ip(domain).await, None)
+        }
+    }
+}
+
+/// Combines the answers to the A and AAAA queries into the answer the domain
+/// is checked with. An answer that's MaybeVulnerable or failed on its own
+/// wins, so each IP version is checked, and otherwise the addresses of both
+/// are kept.
+fn by_ip_version(
+    a: Result<LookupIp, ResolveError>,
+    aaaa: Result<LookupIp, ResolveError>,
+    options: &InspectOptions,
+) -> (Result<LookupIp, ResolveError>, Option<IpVersions>) {
+    let versions = IpVersions::new(&a, &aaaa, &options.treat_as_vulnerable);
+    let lookup = if versions.a.result == LookupResult::MaybeVulnerable {
+        a
+    } else if versions.aaaa.result == LookupResult::MaybeVulnerable {
+        aaaa
+    } else if versions.a.result == LookupResult::LookupError {
+        a
+    } else if versions.aaaa.result == LookupResult::LookupError {
+        aaaa
+    } else {
+        match (a, aaaa) {
+            (Ok(a), Ok(aaaa)) => {
+                let records: Vec<Record> = a
+                    .as_lookup()
+                    .records()
+                    .iter()
+                    .chain(aaaa.as_lookup().records())
+                    .cloned()
+                    .collect();
+                Ok(Lookup::new_with_max_ttl(a.query().clone(), records.into()).into())
+            }
+            (Ok(a), Err(_)) => Ok(a),
+            (Err(_), Ok(aaaa)) => Ok(aaaa),
+            (Err(err), Err(_)) => Err(err),
+        }
+    };
+    (lookup, Some(versions))
+}
+
+/// Fetches the front page of `domain` and checks if it is the page `fp` serves for unclaimed resources.
+fn http_probe(domain: &str, fp: &Fingerprint) -> bool {
+    if fp.body.is_none() {
+        return false;
+    }
+
+    let response = reqwest::blocking::Client::builder()
+        .timeout(HTTP_PROBE_TIMEOUT)
+        .build()
+        .and_then(|client| client.get(format!("http://{}/", domain)).send());
+
+    match response {
+        Ok(response) => {
+            let status = response.status().as_u16();
+            response
+                .text()
+                .map(|body| fp.matches_response(status, &body))
+                .unwrap_or(false)
+        }
+        Err(_) => false,
+    }
+}
+
+/// The asynchronous version of `http_probe`.
+async fn http_probe_async(domain: &str, fp: &Fingerprint) -> bool {
+    if fp.body.is_none() {
+        return false;
+    }
+
+    let client = match reqwest::Client::builder()
+        .timeout(HTTP_PROBE_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client,
+        Err(_) => return false,
+    };
+
+    match client.get(format!("http://{}/", domain)).send().await {
+        Ok(response) => {
+            let status = response.status().as_u16();
+            response
+                .text()
+                .await
+                .map(|body| fp.matches_response(status, &body))
+                .unwrap_or(false)
+        }
+        Err(_) => false,
+    }
+}
+
+/// Logs the error kind a lookup failed with, and the result it maps to.
+fn log_lookup(domain: &str, lookup: &Result<LookupIp, ResolveError>, outcome: &CheckOutcome) {
+    match lookup {
+        Ok(_) => debug!(domain, ips = ?outcome.ips, result = %outcome.result, "lookup succeeded"),
+        Err(err) => debug!(domain, error = ?err.kind(), result = %outcome.result, "lookup failed"),
+    }
+}
+
+/// A lookup that got an answer, but one without any records in it.
+fn is_unresolved(lookup_result: &Result<LookupIp, ResolveError>) -> bool {
+    matches!(lookup_result, Err(err) if matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. }))
+}
+
+/// The CNAME chain of `domain`, starting with the domain itself, together
+/// with the error if it loops back to a name that's already in it or has more
+/// than `max_depth` CNAMEs in it. The chain then ends at the repeated name, or
+/// at the last name that was followed.
+fn follow_cnames(
+    domain: &str,
+    resolver: &impl DnsLookup,
+    max_depth: usize,
+) -> (Vec<String>, Option<ErrorKind>) {
+    let mut chain = CnameChain::new(domain);
+    loop {
+        let lookup = resolver.lookup(chain.last(), RecordType::CNAME);
+        if let ControlFlow::Break(broken) = chain.follow(domain, cname_target(&lookup), max_depth) {
+            return (chain.names, broken);
+        }
+    }
+}
+
+/// The asynchronous version of `follow_cnames`.
+async fn follow_cnames_async(
+    domain: &str,
+    resolver: &impl AsyncDnsLookup,
+    max_depth: usize,
+) -> (Vec<String>, Option<ErrorKind>) {
+    let mut chain = CnameChain::new(domain);
+    loop {
+        let lookup = resolver.lookup(chain.last(), RecordType::CNAME).await;
+        if let ControlFlow::Break(broken) = chain.follow(domain, cname_target(&lookup), max_depth) {
+            return (chain.names, broken);
+        }
+    }
+}
+
+/// A CNAME chain while it's being followed, with the names seen so far to find loops.
+struct CnameChain {
+    names: Vec<String>,
+    seen: HashSet<String>,
+}
+
+impl CnameChain {
+    fn new(domain: &str) -> CnameChain {
+        let mut chain = CnameChain {
+            names: vec![],
+            seen: HashSet::new(),
+        };
+        chain.push(domain.to_string());
+        chain
+    }
+
+    fn last(&self) -> &str {
+        &self.names[self.names.len() - 1]
+    }
+
+    /// Adds `name` to the end of the chain, and returns false if it was already in it.
+    fn push(&mut self, name: String) -> bool {
+        let key = name.strip_suffix('.').unwrap_or(&name).to_ascii_lowercase();
+        self.names.push(name);
+        self.seen.insert(key)
+    }
+
+    /// Adds the `target` of the CNAME of the last name to the chain. Breaks
+    /// once the chain has ended, with the error if it can't be followed.
+    fn follow(
+        &mut self,
+        domain: &str,
+        target: Option<String>,
+        max_depth: usize,
+    ) -> ControlFlow<Option<ErrorKind>> {
+        let Some(target) = target else {
+            return ControlFlow::Break(None);
+        };
+        if self.names.len() > max_depth {
+            return ControlFlow::Break(Some(ErrorKind::CnameTooLong));
+        }
+        trace!(domain, target, "followed CNAME");
+        if !self.push(target) {
+            return ControlFlow::Break(Some(ErrorKind::CnameLoop));
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// The names a CNAME chain leads to, without the domain it starts at and the trailing dots.
+fn chain_targets(chain: Vec<String>) -> Vec<String> {
+    chain
+        .into_iter()
+        .skip(1)
+        .map(|name| name.strip_suffix('.').map(str::to_string).unwrap_or(name))
+        .collect()
+}
+
+fn cname_target(lookup: &Result<Lookup, ResolveError>) -> Option<String> {
+    lookup
+        .as_ref()
+        .ok()?
+        .record_iter()
+        .find_map(|r| match r.data() {
+            Some(RData::CNAME(name)) => Some(name.to_ascii()),
+            _ => None,
+        })
+}
+
+/// The fingerprint matching the end of the CNAME chain, if the chain has any CNAMEs in it.
+fn matching_provider<'a>(
+    chain: &[String],
+    fingerprints: &'a [Fingerprint],
+) -> Option<&'a Fingerprint> {
+    if chain.len() < 2 {
+        return None;
+    }
+
+    let target = &chain[chain.len() - 1];
+    fingerprints.iter().find(|fp| fp.matches(target))
+}
@@ -0,0 +1,185 @@
+use crate::direct::{self, DirectOptions};
+use rand::Rng;
+use std::future::Future;
+use std::net::IpAddr;
+use trust_dns_resolver::error::ResolveError;
+use trust_dns_resolver::lookup::Lookup;
+use trust_dns_resolver::lookup_ip::LookupIp;
+use trust_dns_resolver::proto::op::Message;
+use trust_dns_resolver::proto::rr::{Name, RecordType};
+use trust_dns_resolver::Resolver;
+use trust_dns_resolver::TokioAsyncResolver;
+
+/// The queries the checks need to make, so they can be run against
+/// something other than a real resolver.
+pub trait DnsLookup {
+    /// Looks up the A and AAAA records of `name`.
+    fn lookup_ip(&self, name: &str) -> Result<LookupIp, ResolveError>;
+    /// Looks up the records of type `record_type` of `name`.
+    fn lookup(&self, name: &str, record_type: RecordType) -> Result<Lookup, ResolveError>;
+    /// Asks the name server at `ip` itself for the records of type
+    /// `record_type` of `name`, without recursion, and returns its answer.
+    fn query_name_server(
+        &self,
+        ip: IpAddr,
+        name: &str,
+        record_type: RecordType,
+        options: &DirectOptions,
+    ) -> Result<Message, ResolveError>;
+}
+
+/// The asynchronous version of `DnsLookup`.
+pub trait AsyncDnsLookup {
+    /// Looks up the A and AAAA records of `name`.
+    fn lookup_ip(&self, name: &str) -> impl Future<Output = Result<LookupIp, ResolveError>>;
+    /// Looks up the records of type `record_type` of `name`.
+    fn lookup(
+        &self,
+        name: &str,
+        record_type: RecordType,
+    ) -> impl Future<Output = Result<Lookup, ResolveError>>;
+    /// Asks the name server at `ip` itself for the records of type
+    /// `record_type` of `name`, without recursion, and returns its answer.
+    fn query_name_server(
+        &self,
+        ip: IpAddr,
+        name: &str,
+        record_type: RecordType,
+        options: &DirectOptions,
+    ) -> impl Future<Output = Result<Message, ResolveError>>;
+}
+
+/// The name to send to the name servers. trust-dns lowercases the names it
+/// gets as strings while it converts them from unicode, so ASCII names are
+/// converted here to keep the case they are given in.
+fn query_name(name: &str) -> Result<Name, ResolveError> {
+    if name.is_ascii() {
+        Ok(Name::from_ascii(name)?)
+    } else {
+        Ok(Name::from_utf8(name)?)
+    }
+}
+
+impl DnsLookup for Resolver {
+    fn lookup_ip(&self, name: &str) -> Result<LookupIp, ResolveError> {
+        // addresses are answered without a query, which only works for strings
+        if name.parse::<IpAddr>().is_ok() {
+            return Resolver::lookup_ip(self, name);
+        }
+        Resolver::lookup_ip(self, query_name(name)?)
+    }
+
+    fn lookup(&self, name: &str, record_type: RecordType) -> Result<Lookup, ResolveError> {
+        Resolver::lookup(self, query_name(name)?, record_type)
+    }
+
+    fn query_name_server(
+        &self,
+        ip: IpAddr,
+        name: &str,
+        record_type: RecordType,
+        options: &DirectOptions,
+    ) -> Result<Message, ResolveError> {
+        direct::query(ip, &query_name(name)?, record_type, options)
+    }
+}
+
+impl AsyncDnsLookup for TokioAsyncResolver {
+    async fn lookup_ip(&self, name: &str) -> Result<LookupIp, ResolveError> {
+        if name.parse::<IpAddr>().is_ok() {
+            return TokioAsyncResolver::lookup_ip(self, name).await;
+        }
+        TokioAsyncResolver::lookup_ip(self, query_name(name)?).await
+    }
+
+    async fn lookup(&self, name: &str, record_type: RecordType) -> Result<Lookup, ResolveError> {
+        TokioAsyncResolver::lookup(self, query_name(name)?, record_type).await
+    }
+
+    async fn query_name_server(
+        &self,
+        ip: IpAddr,
+        name: &str,
+        record_type: RecordType,
+        options: &DirectOptions,
+    ) -> Result<Message, ResolveError> {
+        direct::query_async(ip, &query_name(name)?, record_type, options).await
+    }
+}
+
+/// Wraps a resolver and sends the names with the case of each letter picked
+/// at random, like the 0x20 technique does. Only the case on the wire is
+/// randomized: the case of the name in the answers isn't checked against
+/// it, as the resolver doesn't hand the question of the answer over, so this
+/// on its own doesn't protect against spoofed answers. Name servers answer
+/// the same for any case, so the results don't change. Passes the names on
+/// as they are when it isn't `enabled`.
+pub struct RandomizeCase<'a, R> {
+    resolver: &'a R,
+    enabled: bool,
+}
+
+impl<'a, R> RandomizeCase<'a, R> {
+    pub fn new(resolver: &'a R, enabled: bool) -> RandomizeCase<'a, R> {
+        RandomizeCase { resolver, enabled }
+    }
+
+    fn name(&self, name: &str) -> String {
+        if !self.enabled {
+            return name.to_string();
+        }
+        let mut rng = rand::thread_rng();
+        name.chars()
+            .map(|c| {
+                if rng.gen_bool(0.5) {
+                    c.to_ascii_uppercase()
+                } else {
+                    c.to_ascii_lowercase()
+                }
+            })
+            .collect()
+    }
+}
+
+impl<R: DnsLookup> DnsLookup for RandomizeCase<'_, R> {
+    fn lookup_ip(&self, name: &str) -> Result<LookupIp, ResolveError> {
+        self.resolver.lookup_ip(&self.name(name))
+    }
+
+    fn lookup(&self, name: &str, record_type: RecordType) -> Result<Lookup, ResolveError> {
+        self.resolver.lookup(&self.name(name), record_type)
+    }
+
+    fn query_name_server(
+        &self,
+        ip: IpAddr,
+        name: &str,
+        record_type: RecordType,
+        options: &DirectOptions,
+    ) -> Result<Message, ResolveError> {
+        self.resolver
+            .query_name_server(ip, &self.name(name), record_type, options)
+    }
+}
+
+impl<R: AsyncDnsLookup> AsyncDnsLookup for RandomizeCase<'_, R> {
+    async fn lookup_ip(&self, name: &str) -> Result<LookupIp, ResolveError> {
+        self.resolver.lookup_ip(&self.name(name)).await
+    }
+
+    async fn lookup(&self, name: &str, record_type: RecordType) -> Result<Lookup, ResolveError> {
+        self.resolver.lookup(&self.name(name), record_type).await
+    }
+
+    async fn query_name_server(
+        &self,
+        ip: IpAddr,
+        name: &str,
+        record_type: RecordType,
+        options: &DirectOptions,
+    ) -> Result<Message, ResolveError> {
+        self.resolver
+            .query_name_server(ip, &self.name(name), record_type, options)
+            .await
+    }
+}
@@ -1,232 +1,3349 @@
-use anyhow::Result;
-use clap::Parser;
-use serde::Serialize;
-use std::collections::HashMap;
+use anyhow::{anyhow, Context, Result};
+use clap::builder::RangedU64ValueParser;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use flate2::read::MultiGzDecoder;
+use indexmap::{IndexMap, IndexSet};
+use indicatif::{ProgressBar, ProgressStyle};
+use nsdetect::direct::DirectOptions;
+use nsdetect::dispatch::{dispatch, DispatchOptions};
+use nsdetect::fingerprints::{default_fingerprints, load_fingerprints};
+use nsdetect::lookup::{AsyncDnsLookup, DnsLookup, RandomizeCase};
+use nsdetect::psl::load_public_suffix_list;
+use nsdetect::replay::{Recorder, Replayer};
+use nsdetect::single_flight::SingleFlight;
+use nsdetect::{
+    ascii_domain, compare_answers, inspect_domain, inspect_domain_async, normalize_domain,
+    response_code_by_name, CheckOutcome, ErrorKind, InspectOptions, LookupResult, Severity,
+    MAX_CNAME_DEPTH,
+};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::de::value::StrDeserializer;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
 use std::fmt::Display;
-use std::io::{BufRead, Write};
-use std::net::IpAddr;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::rc::Rc;
 use std::str::FromStr;
-use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use termcolor::{Color, ColorChoice, ColorSpec, NoColor, StandardStream, WriteColor};
 use tokio::runtime::Runtime;
+use tracing::{debug, info, Level};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::prelude::*;
 use trust_dns_resolver::config::*;
-use trust_dns_resolver::error::ResolveError;
-use trust_dns_resolver::error::ResolveErrorKind;
-use trust_dns_resolver::lookup_ip::LookupIp;
 use trust_dns_resolver::proto::op::ResponseCode;
+use trust_dns_resolver::proto::rr::RecordType;
+use trust_dns_resolver::system_conf::read_system_conf;
 use trust_dns_resolver::Resolver;
 use trust_dns_resolver::TokioAsyncResolver;
+use url::Url;
 
-#[derive(PartialEq, Serialize)]
-enum LookupResult {
-    Safe,
-    MaybeVulnerable,
-    LookupError,
+/// Tool to detect if a domain is vulnerable to domain server takeover.
+/// If neither of -d or -i is specified, the list of domains will be read
+/// from stdin. Without a subcommand the domains are scanned, like with scan.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(flatten)]
+    args: Args,
 }
 
-impl Display for LookupResult {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        match self {
-            LookupResult::Safe => write!(f, "Safe"),
-            LookupResult::MaybeVulnerable => write!(f, "MaybeVulnerable"),
-            LookupResult::LookupError => write!(f, "LookupError"),
-        }
-    }
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check the domains, which is also what happens without a subcommand
+    Scan(Box<Args>),
+    /// Compare two files with json results and print the domains that were added, removed or changed result
+    Diff(DiffArgs),
+    /// Print a completion script for this shell to stdout
+    Completions {
+        #[clap(value_enum)]
+        shell: Shell,
+    },
+    /// Show the built-in fingerprints of the providers
+    Fingerprints {
+        #[clap(subcommand)]
+        command: FingerprintsCommand,
+    },
 }
 
-fn is_vulnerable(lookup_result: &Result<LookupIp, ResolveError>) -> LookupResult {
-    match lookup_result {
-        Ok(_) => LookupResult::Safe,
-        Err(err) => match err.kind() {
-            ResolveErrorKind::NoRecordsFound {
-                response_code: ResponseCode::ServFail,
-                ..
-            } => LookupResult::MaybeVulnerable,
-            ResolveErrorKind::Io(_) => LookupResult::LookupError,
-            ResolveErrorKind::Proto(_) => LookupResult::LookupError,
-            _ => LookupResult::Safe,
-        },
-    }
+#[derive(clap::Args, Debug)]
+struct DiffArgs {
+    /// The json results of the earlier scan
+    old: PathBuf,
+    /// The json results of the later scan
+    new: PathBuf,
+    /// How to print the differences, text or json
+    #[clap(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+    /// Write the differences to this file instead of stdout
+    #[clap(short, long)]
+    output: Option<PathBuf>,
 }
 
-/// Tool to detect if a domain is vulnerable to domain server takeover.
-/// If neither of -d or -i is specified, the list of domains will be read
-/// from stdin.
-#[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
+#[derive(Subcommand, Debug)]
+enum FingerprintsCommand {
+    /// Print the service and CNAME suffix of each built-in fingerprint, one per line
+    List,
+}
+
+#[derive(clap::Args, Debug)]
 struct Args {
-    /// Filename with a list of domains to check
+    /// Read options from this toml file, with the names of the long options as keys, like concurrency = 100 or name_server = "10.0.0.2". Options from the command line or the environment override the ones in the file
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// Filename with a list of domains to check, can be given more than once. Files ending in .gz are decompressed, and http and https urls are downloaded
     #[clap(short, long)]
-    input_file: Option<String>,
-    /// Domain to do the lookup for
+    input_file: Vec<String>,
+    /// Bearer token to download input lists with
+    #[clap(
+        long,
+        env = "NSDETECT_INPUT_TOKEN",
+        hide_env_values = true,
+        conflicts_with = "input_basic_auth"
+    )]
+    input_token: Option<String>,
+    /// User and password to download input lists with, as user:password
+    #[clap(long, env = "NSDETECT_INPUT_BASIC_AUTH", hide_env_values = true)]
+    input_basic_auth: Option<String>,
+    /// Domain to do the lookup for, can be given more than once
     #[clap(short, long)]
-    domain: Option<String>,
+    domain: Vec<String>,
     /// If the lookups should be perfomed asynchronously or not
     #[clap(short, long)]
     r#async: bool,
-    /// If the output should be printed in color or not
+    /// How many lookups to have in flight at the same time in async mode
+    #[clap(long, env = "NSDETECT_CONCURRENCY", default_value_t = 50, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    concurrency: usize,
+    /// How many lookups to run at the same time in sync mode, each on its own thread. The results are still printed in input order
+    #[clap(long, env = "NSDETECT_JOBS", default_value_t = 1, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    jobs: usize,
+    /// Wait for all lookups to finish in async mode and print the results in input order, instead of as they complete
+    #[clap(long)]
+    ordered: bool,
+    /// Maximum number of queries per second to send in async mode
+    #[clap(long, env = "NSDETECT_RATE", value_parser = parse_rate)]
+    rate: Option<f64>,
+    /// Slow down in async mode when a large share of the answers are SERVFAIL, which is how some resolvers rate limit, and speed up again when it drops
+    #[clap(long)]
+    adaptive_throttle: bool,
+    /// Do the lookups in async mode in batches of this many domains, where each batch is done before the next one starts
+    #[clap(long, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    batch_size: Option<usize>,
+    /// How long to wait between the batches of --batch-size, like 10s or 1m
+    #[clap(long, value_parser = parse_interval, requires = "batch_size")]
+    batch_pause: Option<Duration>,
+    /// Always print the output in color, by default it's colored when stdout is a terminal and NO_COLOR isn't set. Never applies when writing to a file
     #[clap(short, long)]
     color: bool,
-    /// Combined option json_input and json_output, if this one is set, then those are assumed to be set also
-    #[clap(short, long)]
-    json: bool,
+    /// Never print the output in color
+    #[clap(long, conflicts_with = "color")]
+    no_color: bool,
+    /// How to print the results, ndjson is one json object per line and csv has a domain,result,severity,response_code,provider,dangling_cname,lame_name_server,unregistered_ns_domain,ips,duration_ms header, html is a standalone page with a table of the results markdown is a table to paste into issues and sarif is a SARIF 2.1.0 log with the MaybeVulnerable domains. In text, ndjson, csv and markdown the results are printed as soon as each lookup completes
+    #[clap(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
     /// If the input should be parsed as json
     #[clap(long)]
     json_input: bool,
-    /// If the output should be printed as json, in case both this value and --color is set at the same time, this one takes precedence
+    /// If the input should be parsed as one json object per line, with the domain in its domain field
+    #[clap(long, conflicts_with = "json_input")]
+    ndjson_input: bool,
+    /// Check every domain in the input, instead of skipping the ones that were already seen ignoring case and trailing dots
+    #[clap(long)]
+    no_dedup: bool,
+    /// Stop with an error at the first entry in the input that isn't a valid domain, instead of reporting it as InvalidInput
     #[clap(long)]
+    strict: bool,
+    /// Deprecated, use --format json --json-input
+    #[clap(short, long, hide = true, conflicts_with = "format")]
+    json: bool,
+    /// Deprecated, use --format json
+    #[clap(long, hide = true, conflicts_with = "format")]
     json_output: bool,
-    /// The ip address of the name server to use, defaults to google's servers
-    #[clap(short, long)]
+    /// Deprecated, use --format ndjson
+    #[clap(long, hide = true, conflicts_with_all = ["format", "json", "json_output"])]
+    ndjson: bool,
+    /// Deprecated, use --format csv
+    #[clap(long, hide = true, conflicts_with_all = ["format", "json", "json_output", "ndjson"])]
+    csv: bool,
+    /// Comma separated response codes, like refused,notauth, to report as MaybeVulnerable on top of servfail
+    #[clap(long, value_delimiter = ',', value_parser = parse_response_code)]
+    treat_as_vulnerable: Vec<ResponseCode>,
+    /// Comma separated ip addresses or host names of the name servers to use, defaults to google's servers. Host names are resolved with the system resolver, and all of their addresses are used
+    #[clap(short, long, env = "NSDETECT_NAME_SERVER")]
     name_server: Option<String>,
+    /// Use the name servers of a well known public DNS provider, or the ones from resolv.conf with system
+    #[clap(
+        long,
+        env = "NSDETECT_RESOLVER",
+        value_enum,
+        conflicts_with = "name_server"
+    )]
+    resolver: Option<ResolverPreset>,
+    /// Send the queries with DNS-over-HTTPS on port 443 instead of plain DNS on port 53
+    #[clap(long)]
+    doh: bool,
+    /// Send the queries with DNS-over-TLS on port 853 instead of plain DNS on port 53
+    #[clap(long, conflicts_with = "doh")]
+    dot: bool,
+    /// Send the queries over TCP instead of UDP, which avoids truncated responses
+    #[clap(long, conflicts_with_all = ["doh", "dot"])]
+    tcp: bool,
+    /// The port the name servers listen on, defaults to 53, or 443 with --doh and 853 with --dot
+    #[clap(long, env = "NSDETECT_PORT", value_parser = clap::value_parser!(u16).range(1..))]
+    port: Option<u16>,
+    /// Save the responses of the name servers to this directory, so the scan can be run again with --replay
+    #[clap(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+    /// Answer the queries with the responses saved with --record in this directory, instead of asking any name servers
+    #[clap(long)]
+    replay: Option<PathBuf>,
+    /// Send the queries from this local ip address, for hosts with more than one network interface
+    #[clap(long)]
+    bind: Option<IpAddr>,
+    /// The name in the certificate of the name servers, needed for --doh and --dot with servers that aren't a known public provider
+    #[clap(long)]
+    tls_name: Option<String>,
+    /// Confirm CNAMEs to known providers that still resolve by looking for the provider's error page over HTTP
+    #[clap(long)]
+    http_probe: bool,
+    /// File with extra fingerprints to look for, either as json or toml
+    #[clap(long)]
+    fingerprints: Option<PathBuf>,
+    /// Only use the fingerprints from --fingerprints instead of adding them to the built-in ones
+    #[clap(long, requires = "fingerprints")]
+    replace_fingerprints: bool,
+    /// File with the public suffix list to use instead of the bundled one, in the same format as https://publicsuffix.org/list/public_suffix_list.dat
+    #[clap(long)]
+    psl: Option<PathBuf>,
+    /// File with domains, one per line, whose findings are known exceptions and reported as Safe, marked as suppressed. A line like *.example.com matches every subdomain of example.com
+    #[clap(long)]
+    allowlist: Option<PathBuf>,
+    /// Only print the domains with this result, the summary still counts all of them
+    #[clap(long, value_enum)]
+    filter: Option<Filter>,
+    /// Only print the domains that might be vulnerable, same as --filter vulnerable
+    #[clap(long, conflicts_with = "filter")]
+    only_vulnerable: bool,
+    /// Only print the domains whose lookup failed, to debug the resolver setup. Unlike --filter error that includes the timeouts, which are reported as Safe. With comma separated error kinds, like --only-errors=timeout,io, only the domains that failed with one of them are printed. The kinds are timeout, io, proto, no_connections, other, cname_loop and cname_too_long
+    #[clap(long, num_args = 0..=1, require_equals = true, value_delimiter = ',', value_parser = parse_error_kind, conflicts_with_all = ["filter", "only_vulnerable"])]
+    only_errors: Option<Vec<ErrorKind>>,
+    /// Write the results to this file instead of stdout
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+    /// Append the results to the --output file as they complete instead of overwriting it, and sync it to disk every second, so a scan that crashes keeps what it found. Only works with --format ndjson
+    #[clap(long, requires = "output", conflicts_with_all = ["sort", "ordered", "shuffle", "watch"])]
+    append: bool,
+    /// Only print the domains that might be vulnerable, whose lookup failed or that are Inconsistent, unless --filter is given, and don't print the progress bar or the summary line to stderr
+    #[clap(short, long)]
+    quiet: bool,
+    /// Log the queries and how their answers were interpreted to stderr, -vv also logs the DNS exchanges
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// How to write the log lines to stderr, json is one json object per line and also logs the result of each domain without -v
+    #[clap(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+    /// Always exit with 0 on a completed scan, instead of 2 when a domain might be vulnerable and 1 when a lookup failed or timed out
+    #[clap(long)]
+    no_fail: bool,
+    /// How many domains that might be vulnerable it takes to exit with 2
+    #[clap(long, default_value_t = 1, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    fail_threshold: usize,
+    /// Sort the output alphabetically by domain instead of keeping the input order
+    #[clap(long)]
+    sort: bool,
+    /// Stop the scan after this long, like 90s, 30m or 2h, and report the lookups that are still in flight and the domains that haven't been looked up yet as TimedOut
+    #[clap(long, value_parser = parse_interval)]
+    max_runtime: Option<Duration>,
+    /// How many seconds to wait for an answer to each query, fractions are allowed. Lookups that time out are reported as Safe
+    #[clap(long, env = "NSDETECT_TIMEOUT", value_parser = parse_seconds)]
+    timeout: Option<Duration>,
+    /// Print the domains that would be looked up, after normalizing, deduplicating and validating the input, and exit without sending any queries
+    #[clap(long)]
+    dry_run: bool,
+    /// Compare two files with json results, the same as the diff subcommand
+    #[clap(long, num_args = 2, value_names = ["OLD", "NEW"], hide = true)]
+    diff: Vec<PathBuf>,
+    /// Json file to keep the results in between runs, domains with a result in it that's younger than --cache-ttl aren't looked up again. With --watch that's only the case in the first round
+    #[clap(long)]
+    cache: Option<PathBuf>,
+    /// The json or ndjson results of an interrupted scan, the domains in it aren't looked up again and their results are included with the new ones. With --append they aren't written again
+    #[clap(long, conflicts_with = "watch")]
+    resume: Option<PathBuf>,
+    /// How old results in the --cache file can be before the domain is looked up again, like 30m, 12h or 86400
+    #[clap(long, default_value = "24h", value_parser = parse_interval, requires = "cache")]
+    cache_ttl: Duration,
+    /// Keep checking the domains again after this interval, like 30s, 5m or 1h, and only print the ones whose result changed
+    #[clap(long, value_parser = parse_interval)]
+    watch: Option<Duration>,
+    /// Write the counts of the scan as Prometheus gauges to this file when it's done, for the textfile collector of node_exporter
+    #[clap(long)]
+    metrics: Option<PathBuf>,
+    /// Write the CNAME chains of the domains to this file as a Graphviz DOT graph, with the dangling targets in red
+    #[clap(long, conflicts_with = "watch")]
+    graph: Option<PathBuf>,
+    /// How many CNAME records to follow, domains with a longer chain or one that loops are reported as LookupError with the chain
+    #[clap(long, default_value_t = MAX_CNAME_DEPTH, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    max_cname_depth: usize,
+    /// How many times to retry a query that timed out or couldn't be sent
+    #[clap(long, env = "NSDETECT_ATTEMPTS")]
+    attempts: Option<usize>,
+    /// Send the queries with EDNS, which advertises a 1232 byte buffer for the answers. It's off by default, unless resolv.conf turns it on with --resolver system
+    #[clap(long)]
+    edns: bool,
+    /// Send the queries without EDNS, also with --resolver system when resolv.conf turns it on, for name servers and middleboxes that don't handle it
+    #[clap(long, conflicts_with = "edns")]
+    no_edns: bool,
+    /// Randomize the case of the letters in the names that are sent to the name servers, like the 0x20 technique. Only the case on the wire is randomized, the case in the answers isn't verified
+    #[clap(long)]
+    randomize_case: bool,
+    /// Look up the domains in a random order, so sorted input doesn't send all the queries for one zone back to back. The whole input is read first, and the results are still printed in input order
+    #[clap(long)]
+    shuffle: bool,
+    /// The seed for --shuffle, to look up the domains in the same order every time
+    #[clap(long, requires = "shuffle")]
+    seed: Option<u64>,
+    /// Print a completion script for this shell to stdout
+    #[clap(long, value_enum, hide = true)]
+    generate_completions: Option<Shell>,
+    /// How many times to look up a domain again after a SERVFAIL answer, it's only reported as MaybeVulnerable if every answer is SERVFAIL
+    #[clap(long, default_value_t = 0)]
+    servfail_retries: usize,
+    /// How many times to look up a domain again after an io error, waiting 100ms before the first retry and twice as long before each one after it. It's only reported as LookupError if every lookup fails
+    #[clap(long, default_value_t = 2)]
+    io_retries: usize,
+    /// Query this record type instead of the A and AAAA records
+    #[clap(long, value_enum, ignore_case = true)]
+    record_type: Option<QueryType>,
+    /// Query the A and AAAA records separately and report the answer for each IP version, a domain might be vulnerable if either of them does
+    #[clap(long, conflicts_with = "record_type")]
+    both_ip_versions: bool,
+    /// Also query each name server the domains are delegated to directly, and report the domains where one of them refuses, fails or isn't authoritative
+    #[clap(long)]
+    lame_delegation: bool,
+    /// Also check that the domains of the name servers the domains are delegated to are registered, and report the domains where one of them isn't
+    #[clap(long)]
+    unregistered_ns: bool,
+    /// Look up the domains that might be vulnerable again with a second resolver, and only report them if it agrees. Not with --replay, which has no second resolver to ask
+    #[clap(long, conflicts_with = "replay")]
+    confirm: bool,
+    /// The ip address of the name server to confirm the results with, defaults to cloudflare's, or google's when --resolver cloudflare is used
+    #[clap(long, requires = "confirm")]
+    confirm_resolver: Option<IpAddr>,
+    /// Look up each domain with every one of the --name-server name servers on its own, and report the domains they disagree about, like one answering NXDOMAIN and another an address, as Inconsistent. The queries use plain DNS, or TCP with --tcp
+    #[clap(long, requires = "name_server", conflicts_with_all = ["doh", "dot", "record", "replay"])]
+    compare: bool,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum QueryType {
+    A,
+    Aaaa,
+    Cname,
+    Mx,
+    Ns,
+    Txt,
+}
+
+impl From<QueryType> for RecordType {
+    fn from(query_type: QueryType) -> Self {
+        match query_type {
+            QueryType::A => RecordType::A,
+            QueryType::Aaaa => RecordType::AAAA,
+            QueryType::Cname => RecordType::CNAME,
+            QueryType::Mx => RecordType::MX,
+            QueryType::Ns => RecordType::NS,
+            QueryType::Txt => RecordType::TXT,
+        }
+    }
 }
 
-fn check_async(to_check: &[String], color: bool, json: bool, ns: Option<String>) -> Result<()> {
-    let io_loop = Runtime::new().unwrap();
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Filter {
+    Vulnerable,
+    Error,
+    Invalid,
+    TimedOut,
+    Inconsistent,
+    Safe,
+    /// The domains that might be vulnerable or failed, which is what --quiet prints
+    #[value(skip)]
+    Problems,
+    /// The domains whose lookup failed, also the timeouts that are reported
+    /// as Safe, which is what --only-errors prints
+    #[value(skip)]
+    Failed,
+}
 
-    let ns = ns.map(|ns| IpAddr::from_str(&ns).unwrap());
+impl Filter {
+    /// If `outcome` is shown with `filter`, and it failed with one of
+    /// `error_kinds`, if there are any.
+    fn matches(filter: Option<Filter>, error_kinds: &[ErrorKind], outcome: &CheckOutcome) -> bool {
+        let result = &outcome.result;
+        let error_kind = error_kinds.is_empty()
+            || outcome
+                .error_kind
+                .is_some_and(|error_kind| error_kinds.contains(&error_kind));
+        error_kind
+            && match filter {
+                Some(Filter::Vulnerable) => *result == LookupResult::MaybeVulnerable,
+                Some(Filter::Error) => *result == LookupResult::LookupError,
+                Some(Filter::Invalid) => *result == LookupResult::InvalidInput,
+                Some(Filter::TimedOut) => *result == LookupResult::TimedOut,
+                Some(Filter::Inconsistent) => *result == LookupResult::Inconsistent,
+                Some(Filter::Safe) => *result == LookupResult::Safe,
+                Some(Filter::Problems) => matches!(
+                    result,
+                    LookupResult::MaybeVulnerable
+                        | LookupResult::LookupError
+                        | LookupResult::Inconsistent
+                ),
+                Some(Filter::Failed) => {
+                    *result == LookupResult::LookupError || outcome.error_kind.is_some()
+                }
+                None => true,
+            }
+    }
+}
 
-    let resolver = io_loop
-        .block_on(async {
-            if let Some(ns) = ns {
-                TokioAsyncResolver::tokio(
-                    ResolverConfig::from_parts(
-                        None,
-                        vec![],
-                        NameServerConfigGroup::from_ips_clear(&[ns], 53, true),
-                    ),
-                    ResolverOpts::default(),
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ResolverPreset {
+    Google,
+    Cloudflare,
+    Quad9,
+    System,
+}
+
+const GOOGLE_IPS: &[IpAddr] = &[
+    IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)),
+    IpAddr::V4(Ipv4Addr::new(8, 8, 4, 4)),
+    IpAddr::V6(Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888)),
+    IpAddr::V6(Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8844)),
+];
+
+const CLOUDFLARE_IPS: &[IpAddr] = &[
+    IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)),
+    IpAddr::V4(Ipv4Addr::new(1, 0, 0, 1)),
+    IpAddr::V6(Ipv6Addr::new(0x2606, 0x4700, 0x4700, 0, 0, 0, 0, 0x1111)),
+    IpAddr::V6(Ipv6Addr::new(0x2606, 0x4700, 0x4700, 0, 0, 0, 0, 0x1001)),
+];
+
+const QUAD9_IPS: &[IpAddr] = &[
+    IpAddr::V4(Ipv4Addr::new(9, 9, 9, 9)),
+    IpAddr::V4(Ipv4Addr::new(149, 112, 112, 112)),
+    IpAddr::V6(Ipv6Addr::new(0x2620, 0x00fe, 0, 0, 0, 0, 0, 0x00fe)),
+    IpAddr::V6(Ipv6Addr::new(0x2620, 0x00fe, 0, 0, 0, 0, 0, 0x0009)),
+];
+
+impl ResolverPreset {
+    /// The addresses of the preset's name servers and the name in their certificates.
+    fn servers(self) -> Option<(&'static [IpAddr], &'static str)> {
+        match self {
+            ResolverPreset::Google => Some((GOOGLE_IPS, "dns.google")),
+            ResolverPreset::Cloudflare => Some((CLOUDFLARE_IPS, "cloudflare-dns.com")),
+            ResolverPreset::Quad9 => Some((QUAD9_IPS, "dns.quad9.net")),
+            ResolverPreset::System => None,
+        }
+    }
+
+    /// The name in the certificate of a public provider's name server.
+    fn tls_name_of(ip: &IpAddr) -> Option<&'static str> {
+        [
+            ResolverPreset::Google,
+            ResolverPreset::Cloudflare,
+            ResolverPreset::Quad9,
+        ]
+        .into_iter()
+        .filter_map(ResolverPreset::servers)
+        .find(|(ips, _)| ips.contains(ip))
+        .map(|(_, tls_name)| tls_name)
+    }
+}
+
+fn parse_rate(rate: &str) -> Result<f64, String> {
+    match rate.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+        Ok(_) => Err("the rate must be a positive number".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    match seconds.parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 && seconds.is_finite() => Ok(Duration::from_secs_f64(seconds)),
+        Ok(_) => Err("the number of seconds must be positive".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parses an interval like 30s, 5m or 1h, a number without a unit is seconds.
+fn parse_interval(interval: &str) -> Result<Duration, String> {
+    let (number, unit) = match interval.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => interval.split_at(i),
+        None => (interval, "s"),
+    };
+    let multiplier = match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return Err(format!("unknown unit {:?}, use s, m or h", unit)),
+    };
+    parse_seconds(number).map(|seconds| seconds.mul_f64(multiplier))
+}
+
+/// Parses the name of a response code, like ServFail, ignoring case.
+fn parse_response_code(name: &str) -> Result<ResponseCode> {
+    let name = name.trim();
+    response_code_by_name(name).ok_or_else(|| anyhow!("unknown response code {:?}", name))
+}
+
+/// Parses the name of an error kind as it's written in the json output, like no_connections.
+fn parse_error_kind(name: &str) -> Result<ErrorKind> {
+    let name = name.trim();
+    ErrorKind::deserialize(StrDeserializer::<serde::de::value::Error>::new(name))
+        .map_err(|_| anyhow!("unknown error kind {:?}", name))
+}
+
+/// Parses a comma separated list of name server addresses and host names,
+/// together with the first host name, which is the name to validate the
+/// certificates of the name servers with if they are encrypted.
+fn parse_name_servers(ns: &str) -> Result<(Vec<IpAddr>, Option<String>)> {
+    let mut ips = vec![];
+    let mut host_name = None;
+    for ns in ns.split(',').map(str::trim) {
+        if let Ok(ip) = IpAddr::from_str(ns) {
+            ips.push(ip);
+            continue;
+        }
+        if ascii_domain(ns).is_none() {
+            return Err(anyhow!(
+                "invalid name server {:?}, it's neither an ip address nor a host name",
+                ns
+            ));
+        }
+        ips.extend(resolve_name_server(ns)?);
+        host_name.get_or_insert_with(|| ns.to_string());
+    }
+    Ok((ips, host_name))
+}
+
+/// Looks up the addresses of a name server given by its host name with the
+/// system resolver, since there is no other resolver to use yet.
+fn resolve_name_server(host_name: &str) -> Result<Vec<IpAddr>> {
+    let mut ips: Vec<IpAddr> = vec![];
+    let addrs = (host_name, 53)
+        .to_socket_addrs()
+        .with_context(|| format!("failed to look up the name server {}", host_name))?;
+    for addr in addrs {
+        if !ips.contains(&addr.ip()) {
+            ips.push(addr.ip());
+        }
+    }
+    if ips.is_empty() {
+        return Err(anyhow!("the name server {} has no addresses", host_name));
+    }
+    debug!(host_name, ?ips, "resolved the name server");
+    Ok(ips)
+}
+
+/// Splits an input entry like example.com@ns.example.com into the domain and
+/// the name server it's looked up with instead of the default ones.
+fn split_name_server(entry: &str) -> (&str, Option<&str>) {
+    match entry.rsplit_once('@') {
+        Some((domain, ns)) if !ns.is_empty() => (domain, Some(ns)),
+        _ => (entry, None),
+    }
+}
+
+/// The configuration that sends the queries only to `ns`, a name server
+/// given for a domain in the input, over the same transport as the others.
+fn entry_config(args: &Args, ns: &str) -> Result<ResolverConfig> {
+    let (ips, tls_name) = name_servers_of(ns)?;
+    let group = name_server_group(args, &ips, tls_name)?;
+    bind(args, ResolverConfig::from_parts(None, vec![], group))
+}
+
+/// The name servers that each domain is looked up with on its own with
+/// --compare, one for each of the comma separated --name-server entries.
+fn compared_name_servers(args: &Args) -> Vec<&str> {
+    match (&args.name_server, args.compare) {
+        (Some(ns), true) => ns.split(',').map(str::trim).collect(),
+        _ => vec![],
+    }
+}
+
+/// Logs what each of the compared name servers answered for `domain`.
+fn log_answers(domain: &str, outcome: &CheckOutcome) {
+    for answer in &outcome.answers {
+        debug!(domain, %answer, "compared answer");
+    }
+}
+
+/// The resolvers for the name servers that domains in the input are looked
+/// up with, created the first time a domain uses one. A name server that no
+/// resolver can be created for is only warned about once.
+struct EntryResolvers<R> {
+    resolvers: RefCell<HashMap<String, Option<Rc<R>>>>,
+}
+
+impl<R> EntryResolvers<R> {
+    fn new() -> EntryResolvers<R> {
+        EntryResolvers {
+            resolvers: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn get(
+        &self,
+        args: &Args,
+        ns: &str,
+        new_resolver: impl FnOnce(ResolverConfig) -> Result<R>,
+    ) -> Option<Rc<R>> {
+        self.resolvers
+            .borrow_mut()
+            .entry(ns.to_string())
+            .or_insert_with(|| match entry_config(args, ns).and_then(new_resolver) {
+                Ok(resolver) => Some(Rc::new(resolver)),
+                Err(err) => {
+                    eprintln!("warning: can't look up domains with {}: {:#}", ns, err);
+                    None
+                }
+            })
+            .clone()
+    }
+}
+
+/// Builds the resolver configuration shared by the sync and async lookups.
+fn resolver_config(args: &Args) -> Result<(ResolverConfig, ResolverOpts)> {
+    let (ips, tls_name) = match (&args.name_server, args.resolver) {
+        (Some(ns), _) => name_servers_of(ns)?,
+        (None, Some(ResolverPreset::System)) => {
+            if args.doh || args.dot || args.port.is_some() {
+                return Err(anyhow!(
+                    "--doh, --dot and --port can't be used with --resolver system"
+                ));
+            }
+            let (config, opts) =
+                read_system_conf().context("failed to read the system resolver configuration")?;
+            let config = if args.tcp {
+                ResolverConfig::from_parts(
+                    config.domain().cloned(),
+                    config.search().to_vec(),
+                    only_tcp(config.name_servers().to_vec().into()),
                 )
             } else {
-                TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+                config
+            };
+            return Ok((bind(args, config)?, apply_opts(args, opts)));
+        }
+        (None, preset) => {
+            let (ips, tls_name) = preset
+                .unwrap_or(ResolverPreset::Google)
+                .servers()
+                .expect("only the system preset lacks servers");
+            (ips.to_vec(), Some(tls_name.to_string()))
+        }
+    };
+
+    let group = name_server_group(args, &ips, tls_name)?;
+    Ok((
+        bind(args, ResolverConfig::from_parts(None, vec![], group))?,
+        apply_opts(args, ResolverOpts::default()),
+    ))
+}
+
+/// The addresses of the comma separated name servers in `ns`, and the name
+/// their certificates are validated with if they are known or given by name.
+fn name_servers_of(ns: &str) -> Result<(Vec<IpAddr>, Option<String>)> {
+    let (ips, host_name) = parse_name_servers(ns)?;
+    let tls_name = ips
+        .first()
+        .and_then(ResolverPreset::tls_name_of)
+        .map(str::to_string)
+        .or(host_name);
+    Ok((ips, tls_name))
+}
+
+/// The name servers at `ips`, over the transport and port that are asked for.
+fn name_server_group(
+    args: &Args,
+    ips: &[IpAddr],
+    tls_name: Option<String>,
+) -> Result<NameServerConfigGroup> {
+    // encrypted transports use their own default port
+    if args.doh || args.dot {
+        // the name is used to validate the certificate of the name servers
+        let tls_name = args.tls_name.clone().or(tls_name).ok_or_else(|| {
+            anyhow!("--tls-name is needed to use --doh or --dot with these name servers")
+        })?;
+        if args.doh {
+            Ok(NameServerConfigGroup::from_ips_https(
+                ips,
+                args.port.unwrap_or(443),
+                tls_name,
+                true,
+            ))
+        } else {
+            Ok(NameServerConfigGroup::from_ips_tls(
+                ips,
+                args.port.unwrap_or(853),
+                tls_name,
+                true,
+            ))
+        }
+    } else if args.tcp {
+        Ok(only_tcp(NameServerConfigGroup::from_ips_clear(
+            ips,
+            args.port.unwrap_or(53),
+            true,
+        )))
+    } else {
+        Ok(NameServerConfigGroup::from_ips_clear(
+            ips,
+            args.port.unwrap_or(53),
+            true,
+        ))
+    }
+}
+
+/// The name servers in `config` as urls like udp://8.8.8.8:53, or the
+/// directory the responses are replayed from, for the json report.
+fn resolver_description(args: &Args, config: &ResolverConfig) -> Vec<String> {
+    if let Some(dir) = &args.replay {
+        return vec![format!("replay of {}", dir.display())];
+    }
+
+    let mut name_servers: Vec<String> = vec![];
+    for ns in config.name_servers() {
+        let name_server = format!("{}://{}", ns.protocol, ns.socket_addr);
+        if !name_servers.contains(&name_server) {
+            name_servers.push(name_server);
+        }
+    }
+    name_servers
+}
+
+/// Makes the queries to the name servers in `config` go out from the address
+/// given with --bind, after checking that it's an address of this host. Name
+/// servers of the other ip version can't be reached from it and are left out.
+fn bind(args: &Args, config: ResolverConfig) -> Result<ResolverConfig> {
+    let Some(ip) = args.bind else {
+        return Ok(config);
+    };
+    std::net::UdpSocket::bind((ip, 0))
+        .with_context(|| format!("can't send queries from {}", ip))?;
+
+    let mut name_servers = config.name_servers().to_vec();
+    name_servers.retain(|ns| ns.socket_addr.is_ipv4() == ip.is_ipv4());
+    if name_servers.is_empty() {
+        return Err(anyhow!(
+            "none of the name servers can be reached from {}, they use another ip version",
+            ip
+        ));
+    }
+    for ns in &mut name_servers {
+        ns.bind_addr = Some(SocketAddr::new(ip, 0));
+    }
+
+    Ok(ResolverConfig::from_parts(
+        config.domain().cloned(),
+        config.search().to_vec(),
+        name_servers,
+    ))
+}
+
+/// The configuration of the resolver that confirms the domains that might be
+/// vulnerable, if --confirm is given. It always uses plain DNS.
+fn confirm_config(args: &Args) -> Result<Option<ResolverConfig>> {
+    if !args.confirm {
+        return Ok(None);
+    }
+
+    let ip = args.confirm_resolver.unwrap_or(match args.resolver {
+        Some(ResolverPreset::Cloudflare) => GOOGLE_IPS[0],
+        _ => CLOUDFLARE_IPS[0],
+    });
+    let group = NameServerConfigGroup::from_ips_clear(&[ip], 53, true);
+    bind(args, ResolverConfig::from_parts(None, vec![], group)).map(Some)
+}
+
+/// Combines the outcome of a domain that might be vulnerable with the outcome
+/// of looking it up again with the confirming resolver. If that resolver
+/// doesn't agree, its outcome is used instead.
+fn confirmed(domain: &str, outcome: CheckOutcome, confirmation: CheckOutcome) -> CheckOutcome {
+    if confirmation.result == LookupResult::MaybeVulnerable {
+        outcome
+    } else {
+        debug!(domain, result = %confirmation.result, "not confirmed by the second resolver");
+        confirmation
+    }
+}
+
+/// Removes everything but the TCP name servers from `group`.
+fn only_tcp(mut group: NameServerConfigGroup) -> NameServerConfigGroup {
+    group.retain(|ns| ns.protocol == Protocol::Tcp);
+    group
+}
+
+/// Applies the query options from the command line on top of `opts`.
+fn apply_opts(args: &Args, mut opts: ResolverOpts) -> ResolverOpts {
+    if let Some(timeout) = args.timeout {
+        opts.timeout = timeout;
+    }
+    if let Some(attempts) = args.attempts {
+        opts.attempts = attempts;
+    }
+    if args.edns {
+        opts.edns0 = true;
+    } else if args.no_edns {
+        opts.edns0 = false;
+    }
+
+    opts
+}
+
+/// A stream of domains to check, or the error that ended the input.
+type Domains = Box<dyn Iterator<Item = Result<String>> + Send>;
+
+/// Set on Ctrl-C, after which no new lookups are started.
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// Makes Ctrl-C stop the scan after the lookups that are in flight, so the
+/// results so far can still be printed. A second Ctrl-C quits right away.
+fn stop_on_ctrl_c() -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("failed to start the tokio runtime")?;
+    std::thread::spawn(move || {
+        runtime.block_on(async {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if STOP.swap(true, Ordering::SeqCst) {
+                    std::process::exit(130);
+                }
+                eprintln!("stopping after the lookups in flight, press Ctrl-C again to quit");
             }
         })
-        .expect("failed to connect resolver");
+    });
+    Ok(())
+}
+
+/// Wraps `resolver` so that its responses are saved to `dir` for --replay.
+fn recorder<R>(resolver: R, dir: &Path) -> Result<Recorder<R>> {
+    Recorder::new(resolver, dir)
+        .with_context(|| format!("failed to create the recording directory {}", dir.display()))
+}
 
-    let futures: Vec<_> = to_check.iter().map(|l| resolver.lookup_ip(l)).collect();
+/// The domains to check, each with the outcome from the cache if it has a
+/// fresh one, in which case the domain isn't looked up again.
+type Pending = Box<dyn Iterator<Item = Result<(String, Option<CheckOutcome>)>> + Send>;
 
-    // do these futures concurrently and return them
-    let results = to_check
-        .iter()
-        .zip(
+fn check_async(
+    to_check: Pending,
+    config: ResolverConfig,
+    opts: ResolverOpts,
+    options: &InspectOptions,
+    args: &Args,
+    on_result: impl FnMut(usize, String, CheckOutcome) -> Result<()>,
+) -> Result<()> {
+    let io_loop = Runtime::new().context("failed to start the tokio runtime")?;
+
+    let resolver = io_loop
+        .block_on(async { TokioAsyncResolver::tokio(config, opts) })
+        .context("failed to connect resolver")?;
+    let confirmer = match confirm_config(args)? {
+        Some(config) => Some(
             io_loop
-                .block_on(futures::future::join_all(futures))
-                .into_iter()
-                .map(|res| is_vulnerable(&res)),
-        )
-        .collect::<HashMap<&String, LookupResult>>();
+                .block_on(async { TokioAsyncResolver::tokio(config, opts) })
+                .context("failed to connect the confirming resolver")?,
+        ),
+        None => None,
+    };
 
-    print_results(results, color, json)
+    match (&args.replay, &args.record) {
+        (Some(dir), _) => check_async_with(
+            &io_loop,
+            to_check,
+            &Replayer::new(dir),
+            confirmer.as_ref(),
+            opts,
+            options,
+            args,
+            on_result,
+        ),
+        (None, Some(dir)) => check_async_with(
+            &io_loop,
+            to_check,
+            &recorder(resolver, dir)?,
+            confirmer.as_ref(),
+            opts,
+            options,
+            args,
+            on_result,
+        ),
+        (None, None) => check_async_with(
+            &io_loop,
+            to_check,
+            &resolver,
+            confirmer.as_ref(),
+            opts,
+            options,
+            args,
+            on_result,
+        ),
+    }
 }
 
-fn check(to_check: &[String], color: bool, json: bool, ns: Option<String>) -> Result<()> {
-    let ns = ns.map(|ns| IpAddr::from_str(&ns).unwrap());
+#[allow(clippy::too_many_arguments)]
+fn check_async_with(
+    io_loop: &Runtime,
+    to_check: Pending,
+    resolver: &impl AsyncDnsLookup,
+    confirmer: Option<&TokioAsyncResolver>,
+    opts: ResolverOpts,
+    options: &InspectOptions,
+    args: &Args,
+    on_result: impl FnMut(usize, String, CheckOutcome) -> Result<()>,
+) -> Result<()> {
+    // lookups of the same name that are in flight at the same time, from
+    // duplicates in the input or CNAME chains that meet, are sent only once
+    let randomized = RandomizeCase::new(resolver, args.randomize_case);
+    let resolver = &SingleFlight::new(&randomized);
+    let entry_resolvers = &EntryResolvers::new();
+    let compared = &compared_name_servers(args);
+    // the names are owned, so that the futures don't borrow from the input
+    let inspect_with = |domain: String, ns: String| async move {
+        match entry_resolvers.get(args, &ns, |config| {
+            TokioAsyncResolver::tokio(config, opts).context("failed to create resolver")
+        }) {
+            Some(entry_resolver) => {
+                let entry_resolver = RandomizeCase::new(&*entry_resolver, args.randomize_case);
+                inspect_domain_async(&domain, &entry_resolver, options).await
+            }
+            None => CheckOutcome::lookup_error(ErrorKind::NoConnections),
+        }
+    };
+    let check = |l: String| async move {
+        let (domain, ns) = split_name_server(&l);
+        let outcome = match ns {
+            Some(ns) => inspect_with(domain.to_string(), ns.to_string()).await,
+            None if !compared.is_empty() => {
+                let outcomes = compared.iter().map(|ns| async move {
+                    (
+                        ns.to_string(),
+                        inspect_with(domain.to_string(), ns.to_string()).await,
+                    )
+                });
+                let outcome = compare_answers(futures::future::join_all(outcomes).await);
+                log_answers(&l, &outcome);
+                outcome
+            }
+            None => inspect_domain_async(domain, resolver, options).await,
+        };
+        match confirmer {
+            Some(confirmer) if outcome.result == LookupResult::MaybeVulnerable => {
+                let confirmation = inspect_domain_async(
+                    domain,
+                    &RandomizeCase::new(confirmer, args.randomize_case),
+                    options,
+                )
+                .await;
+                confirmed(&l, outcome, confirmation)
+            }
+            _ => outcome,
+        }
+    };
 
-    let resolver = if let Some(ns) = ns {
-        Resolver::new(
-            ResolverConfig::from_parts(
-                None,
-                vec![],
-                NameServerConfigGroup::from_ips_clear(&[ns], 53, true),
-            ),
-            ResolverOpts::default(),
-        )
-        .unwrap()
-    } else {
-        Resolver::new(ResolverConfig::default(), ResolverOpts::default()).unwrap()
+    let dispatch_options = DispatchOptions {
+        concurrency: args.concurrency,
+        rate: args.rate,
+        adaptive_throttle: args.adaptive_throttle,
+        batch_size: args.batch_size,
+        batch_pause: args.batch_pause,
+        max_runtime: args.max_runtime,
     };
+    io_loop.block_on(dispatch(
+        to_check,
+        &dispatch_options,
+        &STOP,
+        check,
+        on_result,
+    ))
+}
 
-    let results = to_check
-        .iter()
-        .map(|l| {
-            let is_vulnerable = is_vulnerable(&resolver.lookup_ip(l));
-            (l, is_vulnerable)
-        })
-        .collect::<HashMap<&String, LookupResult>>();
+fn check(
+    to_check: Pending,
+    config: ResolverConfig,
+    opts: ResolverOpts,
+    options: &InspectOptions,
+    args: &Args,
+    on_result: impl FnMut(usize, String, CheckOutcome) -> Result<()>,
+) -> Result<()> {
+    let confirm = confirm_config(args)?;
+    let new_resolver = || Resolver::new(config.clone(), opts).context("failed to create resolver");
 
-    print_results(results, color, json)
+    match (&args.replay, &args.record) {
+        (Some(dir), _) => check_with(
+            to_check,
+            || Ok(Replayer::new(dir)),
+            confirm,
+            opts,
+            options,
+            args,
+            on_result,
+        ),
+        (None, Some(dir)) => check_with(
+            to_check,
+            || recorder(new_resolver()?, dir),
+            confirm,
+            opts,
+            options,
+            args,
+            on_result,
+        ),
+        (None, None) => check_with(
+            to_check,
+            new_resolver,
+            confirm,
+            opts,
+            options,
+            args,
+            on_result,
+        ),
+    }
 }
 
-fn print_results(results: HashMap<&String, LookupResult>, color: bool, json: bool) -> Result<()> {
-    if json {
-        println!("{}", serde_json::to_string_pretty(&results)?);
-    } else {
-        for (domain, is_vulnerable) in results.iter() {
-            print(domain, is_vulnerable, color)?;
+/// Runs the lookups on --jobs threads, that each have their own resolver
+/// from `new_resolver`, and hands the results over on the calling thread.
+fn check_with<R: DnsLookup>(
+    to_check: Pending,
+    new_resolver: impl Fn() -> Result<R> + Sync,
+    confirm: Option<ResolverConfig>,
+    opts: ResolverOpts,
+    options: &InspectOptions,
+    args: &Args,
+    mut on_result: impl FnMut(usize, String, CheckOutcome) -> Result<()>,
+) -> Result<()> {
+    let deadline = args
+        .max_runtime
+        .map(|max_runtime| Instant::now() + max_runtime);
+    // set when the results can't be handed over anymore, so the other
+    // threads stop as well
+    let failed = AtomicBool::new(false);
+    let to_check = Mutex::new(to_check.enumerate());
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    std::thread::scope(|scope| {
+        for _ in 0..args.jobs {
+            let sender = sender.clone();
+            let (new_resolver, confirm, to_check, failed) =
+                (&new_resolver, &confirm, &to_check, &failed);
+            scope.spawn(move || {
+                let resolvers = new_resolver().and_then(|resolver| {
+                    let confirmer = match confirm {
+                        Some(config) => Some(
+                            Resolver::new(config.clone(), opts)
+                                .context("failed to create the confirming resolver")?,
+                        ),
+                        None => None,
+                    };
+                    Ok((resolver, confirmer))
+                });
+                let (resolver, confirmer) = match resolvers {
+                    Ok(resolvers) => resolvers,
+                    Err(err) => {
+                        let _ = sender.send(Err(err));
+                        return;
+                    }
+                };
+                let entry_resolvers = EntryResolvers::new();
+                let compared = compared_name_servers(args);
+                let inspect_with =
+                    |domain: &str, ns: &str| match entry_resolvers.get(args, ns, |config| {
+                        Resolver::new(config, opts).context("failed to create resolver")
+                    }) {
+                        Some(entry_resolver) => inspect_domain(
+                            domain,
+                            &RandomizeCase::new(&*entry_resolver, args.randomize_case),
+                            options,
+                        ),
+                        None => CheckOutcome::lookup_error(ErrorKind::NoConnections),
+                    };
+
+                while !STOP.load(Ordering::SeqCst)
+                    && !failed.load(Ordering::SeqCst)
+                    && deadline.is_none_or(|deadline| Instant::now() < deadline)
+                {
+                    let Some((i, l)) = to_check.lock().expect("input lock poisoned").next() else {
+                        break;
+                    };
+                    let result = l.map(|(l, cached)| {
+                        let outcome = match cached {
+                            Some(outcome) => outcome,
+                            None => {
+                                let (domain, ns) = split_name_server(&l);
+                                let outcome = match ns {
+                                    Some(ns) => inspect_with(domain, ns),
+                                    None if !compared.is_empty() => {
+                                        let outcome = compare_answers(
+                                            compared
+                                                .iter()
+                                                .map(|ns| {
+                                                    (ns.to_string(), inspect_with(domain, ns))
+                                                })
+                                                .collect(),
+                                        );
+                                        log_answers(&l, &outcome);
+                                        outcome
+                                    }
+                                    None => inspect_domain(
+                                        domain,
+                                        &RandomizeCase::new(&resolver, args.randomize_case),
+                                        options,
+                                    ),
+                                };
+                                match &confirmer {
+                                    Some(confirmer)
+                                        if outcome.result == LookupResult::MaybeVulnerable =>
+                                    {
+                                        let confirmation = inspect_domain(
+                                            domain,
+                                            &RandomizeCase::new(confirmer, args.randomize_case),
+                                            options,
+                                        );
+                                        confirmed(&l, outcome, confirmation)
+                                    }
+                                    _ => outcome,
+                                }
+                            }
+                        };
+                        (i, l, outcome)
+                    });
+                    if sender.send(result).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // with more than one job the results come in out of order, so they
+        // are held back until the ones before them have been handed over
+        let mut next = 0;
+        let mut held_back = BTreeMap::new();
+        for result in receiver {
+            let handed_over = result.and_then(|(i, l, outcome)| {
+                held_back.insert(i, (l, outcome));
+                while let Some((l, outcome)) = held_back.remove(&next) {
+                    on_result(next, l, outcome)?;
+                    next += 1;
+                }
+                Ok(())
+            });
+            if handed_over.is_err() {
+                failed.store(true, Ordering::SeqCst);
+                return handed_over;
+            }
+        }
+        // the domains that weren't started before the time ran out
+        if !STOP.load(Ordering::SeqCst)
+            && deadline.is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            for (i, l) in &mut *to_check.lock().expect("input lock poisoned") {
+                let (l, cached) = l?;
+                held_back.insert(i, (l, cached.unwrap_or_else(CheckOutcome::timed_out)));
+            }
+        }
+        // only left when the time ran out or the scan was stopped, with gaps
+        // in between in the latter case
+        for (i, (l, outcome)) in held_back {
+            on_result(i, l, outcome)?;
         }
+        Ok(())
+    })
+}
+
+/// Reads the domains from the input file, the command line or stdin, and the
+/// source json input says they are from. Apart from json input, the lines
+/// are read as they are needed.
+fn input(args: &Args) -> Result<(Domains, Option<String>)> {
+    if !args.input_file.is_empty() {
+        let mut lines: Domains = Box::new(std::iter::empty());
+        for input_file in &args.input_file {
+            let file: Box<dyn Read + Send> =
+                if input_file.starts_with("http://") || input_file.starts_with("https://") {
+                    Box::new(download(args, input_file)?)
+                } else {
+                    Box::new(
+                        File::open(input_file)
+                            .with_context(|| format!("failed to read input file {}", input_file))?,
+                    )
+                };
+            // .gz files are decompressed on the fly
+            let reader: Box<dyn Read + Send> = if input_file.ends_with(".gz") {
+                Box::new(MultiGzDecoder::new(BufReader::new(file)))
+            } else {
+                file
+            };
+            let name = input_file.clone();
+            let file_lines: Domains = Box::new(BufReader::new(reader).lines().map(move |line| {
+                line.with_context(|| format!("failed to read input file {}", name))
+            }));
+            let file_lines = if args.ndjson_input {
+                ndjson_domains(file_lines, input_file.clone(), args.strict)
+            } else {
+                file_lines
+            };
+            lines = Box::new(lines.chain(file_lines));
+        }
+        return Ok((lines, None));
     }
 
-    Ok(())
+    if !args.domain.is_empty() {
+        return Ok((Box::new(args.domain.clone().into_iter().map(Ok)), None));
+    }
+
+    if args.json || args.json_input {
+        let (domains, source) = json_input()?;
+        return Ok((Box::new(domains.into_iter().map(Ok)), source));
+    }
+
+    let lines: Domains = Box::new(
+        BufReader::new(std::io::stdin())
+            .lines()
+            .map(|line| line.context("failed to read from stdin")),
+    );
+    if args.ndjson_input {
+        return Ok((
+            ndjson_domains(lines, "stdin".to_string(), args.strict),
+            None,
+        ));
+    }
+    Ok((lines, None))
+}
+
+/// The json that can be given on stdin, either a list of domains or an
+/// object with the list in its domains field.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonInput {
+    Domains(Vec<String>),
+    Inventory {
+        domains: Vec<String>,
+        /// Where the list comes from, it's included in the json output
+        source: Option<String>,
+    },
 }
 
-fn print(domain: &str, is_vulnerable: &LookupResult, color: bool) -> Result<()> {
-    if color {
-        let mut stdout = StandardStream::stdout(ColorChoice::Always);
-        write!(&mut stdout, "{} : ", domain)?;
+/// Reads the json input from stdin, and returns the domains in it together with their source.
+fn json_input() -> Result<(Vec<String>, Option<String>)> {
+    let input: JsonInput = serde_json::from_reader(std::io::stdin()).context(
+        "failed to parse json from stdin, expected a list of domains or an object with a domains list",
+    )?;
+    Ok(match input {
+        JsonInput::Domains(domains) => (domains, None),
+        JsonInput::Inventory { domains, source } => (domains, source),
+    })
+}
 
-        match *is_vulnerable {
-            LookupResult::MaybeVulnerable => {
-                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?
-            }
-            LookupResult::Safe => stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)))?,
-            LookupResult::LookupError => {
-                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?
+/// A line of --ndjson-input, other fields than the domain and the name server are ignored.
+#[derive(Deserialize)]
+struct NdjsonEntry {
+    domain: String,
+    /// The name server to look up the domain with instead of the default ones
+    name_server: Option<String>,
+}
+
+/// The domains in the json objects on the lines of `source`. Lines that
+/// aren't such an object are skipped with a warning, or end the input with
+/// an error in strict mode.
+fn ndjson_domains(lines: Domains, source: String, strict: bool) -> Domains {
+    Box::new(lines.enumerate().filter_map(move |(i, line)| {
+        let line = match line {
+            Ok(line) if line.trim().is_empty() => return None,
+            Ok(line) => line,
+            Err(err) => return Some(Err(err)),
+        };
+        match serde_json::from_str::<NdjsonEntry>(&line) {
+            Ok(NdjsonEntry {
+                domain,
+                name_server: Some(ns),
+            }) => Some(Ok(format!("{}@{}", domain, ns))),
+            Ok(entry) => Some(Ok(entry.domain)),
+            Err(err) if strict => Some(Err(anyhow!(
+                "invalid entry on line {} of {}: {}",
+                i + 1,
+                source,
+                err
+            ))),
+            Err(err) => {
+                eprintln!(
+                    "warning: skipping the invalid entry on line {} of {}: {}",
+                    i + 1,
+                    source,
+                    err
+                );
+                None
             }
+        }
+    }))
+}
+
+/// Starts the download of an input list, with the credentials from
+/// --input-token or --input-basic-auth. The body is read as it's needed.
+fn download(args: &Args, url: &str) -> Result<reqwest::blocking::Response> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(None)
+        .build()
+        .context("failed to create the http client")?;
+    let mut request = client.get(url);
+    if let Some(token) = &args.input_token {
+        request = request.bearer_auth(token);
+    }
+    if let Some(credentials) = &args.input_basic_auth {
+        let (user, password) = match credentials.split_once(':') {
+            Some((user, password)) => (user, Some(password)),
+            None => (credentials.as_str(), None),
         };
-        writeln!(&mut stdout, "{}", *is_vulnerable)?;
-        stdout.reset()?;
-    } else {
-        println!("{} : {}", domain, *is_vulnerable);
+        request = request.basic_auth(user, password);
     }
 
-    Ok(())
+    request
+        .send()
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("failed to download input list {}", url))
 }
 
-fn main() {
-    let args = Args::parse();
+/// The domain on a line of an input list, or None for blank lines, `#`
+/// comments and lines that are left empty once normalized, like a lone dot.
+fn input_domain(line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    Some(input_entry(line)).filter(|domain| !domain.is_empty())
+}
 
-    if args.input_file.is_some() && args.domain.is_some() {
-        eprintln!("the --input_file option and the --domain option are mutually exclusive");
-        std::process::exit(1);
+/// The normalized domain of an input entry, which is either a bare domain, a
+/// domain with the name server to look it up with like example.com@ns.example.com,
+/// or a URL like https://sub.example.com/path that the host is taken from.
+fn input_entry(entry: &str) -> String {
+    let entry = entry.trim();
+    if !entry.contains("://") {
+        if let (domain, Some(ns)) = split_name_server(entry) {
+            return format!("{}@{}", normalize_domain(domain), normalize_domain(ns));
+        }
     }
+    let host = if entry.contains("://") {
+        Url::parse(entry)
+            .ok()
+            // the url crate encodes international hosts as punycode, turn them
+            // back so the output shows them the same way as bare domains
+            .and_then(|url| url.host_str().map(|host| idna::domain_to_unicode(host).0))
+    } else {
+        None
+    };
+    normalize_domain(host.as_deref().unwrap_or(entry))
+}
+
+/// Orders domains alphabetically, ignoring case and trailing dots.
+fn sort_results(results: &mut [(String, CheckOutcome)]) {
+    results.sort_by_cached_key(|(domain, _)| domain.trim_end_matches('.').to_lowercase());
+}
+
+/// Tally of the results of a scan.
+#[derive(Default, Serialize)]
+struct Summary {
+    /// The source field of the json input
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    scanned: usize,
+    maybe_vulnerable: usize,
+    lookup_error: usize,
+    invalid_input: usize,
+    timed_out: usize,
+    #[serde(skip_serializing_if = "is_zero")]
+    inconsistent: usize,
+    safe: usize,
+}
+
+fn is_zero(count: &usize) -> bool {
+    *count == 0
+}
 
-    let mut to_check: Vec<String> = vec![];
+impl Summary {
+    fn add(&mut self, result: &LookupResult) {
+        self.scanned += 1;
+        match result {
+            LookupResult::MaybeVulnerable => self.maybe_vulnerable += 1,
+            LookupResult::LookupError => self.lookup_error += 1,
+            LookupResult::InvalidInput => self.invalid_input += 1,
+            LookupResult::TimedOut => self.timed_out += 1,
+            LookupResult::Inconsistent => self.inconsistent += 1,
+            LookupResult::Safe => self.safe += 1,
+        }
+    }
 
-    if args.input_file.is_none() && args.domain.is_none() {
-        if args.json || args.json_input {
-            let datas: Vec<String> = serde_json::from_reader(std::io::stdin()).unwrap();
-            to_check.extend(datas);
+    /// Exits with 2 if at least `threshold` domains might be vulnerable, and
+    /// with 1 if any lookup failed or timed out, or the name servers disagree about a domain.
+    fn exit_code(&self, threshold: usize) -> ExitCode {
+        if self.maybe_vulnerable >= threshold {
+            ExitCode::from(2)
+        } else if self.lookup_error > 0 || self.timed_out > 0 || self.inconsistent > 0 {
+            ExitCode::from(1)
         } else {
-            for input in std::io::stdin().lock().lines() {
-                to_check.push(input.unwrap().trim().to_string());
-            }
+            ExitCode::SUCCESS
         }
     }
 
-    if args.input_file.is_some() {
-        let list = std::fs::read_to_string(&args.input_file.unwrap()).unwrap();
+    /// Writes the counts in the Prometheus text format, through a temporary
+    /// file so that a collector never reads a half written one.
+    fn write_metrics(&self, path: &Path, duration: Duration) -> Result<()> {
+        let gauges = [
+            ("nsdetect_total", "Domains scanned", self.scanned as f64),
+            (
+                "nsdetect_vulnerable",
+                "Domains that might be vulnerable",
+                self.maybe_vulnerable as f64,
+            ),
+            (
+                "nsdetect_errors",
+                "Domains whose lookup failed",
+                self.lookup_error as f64,
+            ),
+            (
+                "nsdetect_duration_seconds",
+                "How long the scan took",
+                duration.as_secs_f64(),
+            ),
+        ];
+        let mut metrics = String::new();
+        for (name, help, value) in gauges {
+            metrics.push_str(&format!(
+                "# HELP {} {}\n# TYPE {} gauge\n{} {}\n",
+                name, help, name, name, value
+            ));
+        }
 
-        for l in list.split('\n') {
-            to_check.push(l.trim().to_string());
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, metrics)
+            .and_then(|_| std::fs::rename(&tmp, path))
+            .with_context(|| format!("failed to write metrics to {}", path.display()))
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "Scanned {}: {} MaybeVulnerable, {} LookupError, {} InvalidInput, {} TimedOut, {} Safe",
+            self.scanned,
+            self.maybe_vulnerable,
+            self.lookup_error,
+            self.invalid_input,
+            self.timed_out,
+            self.safe
+        )?;
+        // only scans that compare name servers can have these
+        if self.inconsistent > 0 {
+            write!(f, ", {} Inconsistent", self.inconsistent)?;
         }
-    } else if args.domain.is_some() {
-        to_check.push(args.domain.unwrap());
+        Ok(())
     }
+}
 
-    if args.r#async {
-        check_async(
-            &to_check,
-            args.color,
-            args.json || args.json_output,
-            args.name_server,
-        )
-        .unwrap();
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    /// When the scan was done, in RFC 3339 format
+    generated_at: String,
+    nsdetect_version: &'static str,
+    /// The name servers the lookups were sent to
+    resolver: &'a [String],
+    results: IndexMap<&'a String, &'a CheckOutcome>,
+    summary: &'a Summary,
+}
+
+/// Colors are used on terminals, unless forced on or off, or turned off
+/// with the NO_COLOR environment variable, see https://no-color.org/
+fn use_color(args: &Args) -> bool {
+    if args.no_color {
+        false
+    } else if args.color {
+        true
+    } else if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        false
     } else {
-        check(
-            &to_check,
-            args.color,
-            args.json || args.json_output,
-            args.name_server,
-        )
-        .unwrap();
+        std::io::stdout().is_terminal()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+/// Logs to stderr, -v logs what nsdetect does and -vv adds everything the
+/// resolver does. The json logs also have the result of each domain without -v.
+fn init_logging(verbose: u8, format: LogFormat) {
+    let filter = match (verbose, format) {
+        (0, LogFormat::Text) => return,
+        (0, LogFormat::Json) => Targets::new().with_target("nsdetect", Level::INFO),
+        (1, _) => Targets::new().with_target("nsdetect", Level::DEBUG),
+        _ => Targets::new().with_default(Level::TRACE),
+    };
+    let layer = tracing_subscriber::fmt::layer().with_writer(std::io::stderr);
+    match format {
+        LogFormat::Text => tracing_subscriber::registry()
+            .with(layer.with_ansi(std::io::stderr().is_terminal()))
+            .with(filter)
+            .init(),
+        LogFormat::Json => tracing_subscriber::registry()
+            .with(layer.json().flatten_event(true))
+            .with(filter)
+            .init(),
+    }
+}
+
+/// A progress bar on stderr that counts the completed lookups, out of `len`
+/// if the length of the input is known up front. It's only shown on
+/// terminals, and not with --quiet, when logging or when piping json from
+/// stdout.
+fn progress_bar(args: &Args, format: OutputFormat, len: Option<usize>) -> Result<ProgressBar> {
+    let piped_json =
+        format == OutputFormat::Json && args.output.is_none() && !std::io::stdout().is_terminal();
+    let logging = args.verbose > 0 || args.log_format == LogFormat::Json;
+    if args.quiet || logging || piped_json || !std::io::stderr().is_terminal() {
+        return Ok(ProgressBar::hidden());
+    }
+
+    let progress = match len {
+        Some(len) => ProgressBar::new(len as u64).with_style(ProgressStyle::with_template(
+            "{bar:40} {pos}/{len} ({per_sec}, {eta} left)",
+        )?),
+        None => {
+            let progress = ProgressBar::new_spinner().with_style(ProgressStyle::with_template(
+                "{spinner} {pos} checked ({per_sec})",
+            )?);
+            progress.enable_steady_tick(Duration::from_millis(100));
+            progress
+        }
+    };
+    Ok(progress)
+}
+
+/// Where to print the results, a file is never colored.
+fn output(path: &Option<PathBuf>, color: bool) -> Result<Box<dyn WriteColor>> {
+    match path {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("failed to create output file {}", path.display()))?;
+            Ok(Box::new(NoColor::new(BufWriter::new(file))))
+        }
+        None if color => Ok(Box::new(StandardStream::stdout(ColorChoice::Always))),
+        None => Ok(Box::new(StandardStream::stdout(ColorChoice::Never))),
+    }
+}
+
+/// Opens the output file to append the results to, creating it if it doesn't exist.
+fn append_output(path: &Path) -> Result<Box<dyn WriteColor>> {
+    let mut file = File::options()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open output file {}", path.display()))?;
+    truncate_incomplete_line(&mut file)
+        .with_context(|| format!("failed to open output file {}", path.display()))?;
+    Ok(Box::new(NoColor::new(SyncedFile::new(file))))
+}
+
+/// Drops the last line of the file if it doesn't end with a newline, which
+/// is left behind when a scan crashes while writing a result.
+fn truncate_incomplete_line(file: &mut File) -> std::io::Result<()> {
+    let len = file.seek(SeekFrom::End(0))?;
+    let mut keep = len;
+    let mut byte = [0u8; 1];
+    while keep > 0 {
+        file.seek(SeekFrom::Start(keep - 1))?;
+        file.read_exact(&mut byte)?;
+        if byte[0] == b'\n' {
+            break;
+        }
+        keep -= 1;
+    }
+    if keep < len {
+        file.set_len(keep)?;
+    }
+    Ok(())
+}
+
+/// How often the results appended to the output file are synced to disk.
+const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A buffered file that's written out and synced to disk at the end of a
+/// line once `SYNC_INTERVAL` has passed since the last time, and on flush.
+struct SyncedFile {
+    writer: BufWriter<File>,
+    last_sync: Instant,
+}
+
+impl SyncedFile {
+    fn new(file: File) -> SyncedFile {
+        SyncedFile {
+            writer: BufWriter::new(file),
+            last_sync: Instant::now(),
+        }
+    }
+}
+
+impl Write for SyncedFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        if buf[..written].contains(&b'\n') && self.last_sync.elapsed() >= SYNC_INTERVAL {
+            self.flush()?;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()?;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+    Ndjson,
+    Csv,
+    Html,
+    Markdown,
+    Sarif,
+}
+
+impl OutputFormat {
+    /// If the results can be printed one by one, as the lookups complete.
+    fn is_streamable(self) -> bool {
+        !matches!(
+            self,
+            OutputFormat::Json | OutputFormat::Html | OutputFormat::Sarif
+        )
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => write!(f, "{}", value.get_name()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The format from --format, or from one of the deprecated flags it replaced.
+fn output_format(args: &Args) -> OutputFormat {
+    let deprecated = [
+        (
+            args.json,
+            "--json",
+            "--format json --json-input",
+            OutputFormat::Json,
+        ),
+        (
+            args.json_output,
+            "--json-output",
+            "--format json",
+            OutputFormat::Json,
+        ),
+        (
+            args.ndjson,
+            "--ndjson",
+            "--format ndjson",
+            OutputFormat::Ndjson,
+        ),
+        (args.csv, "--csv", "--format csv", OutputFormat::Csv),
+    ];
+
+    for (set, flag, replacement, format) in deprecated {
+        if set {
+            eprintln!(
+                "warning: {} is deprecated and will be removed in the next release, use {} instead",
+                flag, replacement
+            );
+            return format;
+        }
+    }
+
+    args.format
+}
+
+/// Prints what comes before the first result.
+fn print_header(out: &mut dyn WriteColor, format: OutputFormat) -> Result<()> {
+    if format == OutputFormat::Csv {
+        write_csv_record(
+            out,
+            &[
+                "domain",
+                "result",
+                "severity",
+                "response_code",
+                "provider",
+                "dangling_cname",
+                "lame_name_server",
+                "unregistered_ns_domain",
+                "ips",
+                "duration_ms",
+            ],
+        )?;
+    }
+    if format == OutputFormat::Markdown {
+        writeln!(out, "| Domain | Result | Severity |")?;
+        writeln!(out, "| --- | --- | --- |")?;
+    }
+
+    Ok(())
+}
+
+fn write_csv_record(out: &mut dyn WriteColor, record: &[&str]) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(out);
+    writer.write_record(record)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn print_results(
+    out: &mut dyn WriteColor,
+    results: &[(String, CheckOutcome)],
+    summary: &Summary,
+    resolver: &[String],
+    checked_at: &HashMap<String, SystemTime>,
+    format: OutputFormat,
+    filter: Option<Filter>,
+    error_kinds: &[ErrorKind],
+) -> Result<()> {
+    let shown = results
+        .iter()
+        .filter(|(_, outcome)| Filter::matches(filter, error_kinds, outcome));
+
+    if format == OutputFormat::Json {
+        let report = JsonReport {
+            generated_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            nsdetect_version: env!("CARGO_PKG_VERSION"),
+            resolver,
+            results: shown.map(|(domain, outcome)| (domain, outcome)).collect(),
+            summary,
+        };
+        writeln!(out, "{}", serde_json::to_string_pretty(&report)?)?;
+    } else if format == OutputFormat::Html {
+        print_html(out, shown, summary, checked_at)?;
+    } else if format == OutputFormat::Sarif {
+        writeln!(out, "{}", serde_json::to_string_pretty(&sarif(shown))?)?;
+    } else {
+        for (domain, outcome) in shown {
+            print(out, domain, outcome, format)?;
+        }
+    }
+
+    Ok(())
+}
+
+const HTML_STYLE: &str = "body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; }
+th, td { padding: 0.3em 0.8em; border-bottom: 1px solid #ddd; text-align: left; }
+th { background: #f0f0f0; }
+tr.MaybeVulnerable { background: #fbe3e3; color: #a00000; }
+tr.LookupError, tr.TimedOut { background: #fdf5dc; }
+tr.InvalidInput { color: #888; }
+tr.Inconsistent { background: #e3eefb; }";
+
+/// Prints the results as a standalone html page, with the style inlined so
+/// the file can be shared as it is.
+fn print_html<'a>(
+    out: &mut dyn WriteColor,
+    results: impl Iterator<Item = &'a (String, CheckOutcome)>,
+    summary: &Summary,
+    checked_at: &HashMap<String, SystemTime>,
+) -> Result<()> {
+    let generated_at = humantime::format_rfc3339_seconds(SystemTime::now());
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>nsdetect report</title>")?;
+    writeln!(out, "<style>\n{}\n</style>\n</head>\n<body>", HTML_STYLE)?;
+    writeln!(out, "<h1>nsdetect report</h1>")?;
+    write!(out, "<p>Generated at {}", generated_at)?;
+    if let Some(source) = &summary.source {
+        write!(out, " from {}", html_escape(source))?;
+    }
+    writeln!(out, ".</p>\n<p>{}</p>", html_escape(&summary.to_string()))?;
+    writeln!(out, "<table>")?;
+    writeln!(
+        out,
+        "<tr><th>Domain</th><th>Result</th><th>Severity</th><th>Details</th><th>Addresses</th><th>Duration</th><th>Checked at</th></tr>"
+    )?;
+    for (domain, outcome) in results {
+        let mut details = vec![];
+        if let Some(code) = outcome.response_code {
+            details.push(format!("{:?}", code));
+        }
+        if let Some(error_kind) = outcome.error_kind {
+            details.push(error_kind.to_string());
+        }
+        if let Some(target) = outcome
+            .provider
+            .as_ref()
+            .or(outcome.dangling_cname.as_ref())
+        {
+            details.push(format!("dangling CNAME to {}", target));
+        }
+        if let Some(lame) = &outcome.lame_name_server {
+            details.push(lame.to_string());
+        }
+        if let Some(ns_domain) = &outcome.unregistered_ns_domain {
+            details.push(format!("name server domain {} isn't registered", ns_domain));
+        }
+        let ips: Vec<String> = outcome.ips.iter().map(|ip| ip.to_string()).collect();
+        writeln!(
+            out,
+            "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            outcome.result,
+            html_escape(domain),
+            outcome.result,
+            outcome
+                .severity
+                .map(|severity| severity.to_string())
+                .unwrap_or_default(),
+            html_escape(&details.join(", ")),
+            ips.join(", "),
+            outcome
+                .duration_ms
+                .map(|ms| format!("{}ms", ms))
+                .unwrap_or_default(),
+            checked_at
+                .get(domain)
+                .map(|at| humantime::format_rfc3339_seconds(*at).to_string())
+                .unwrap_or_default(),
+        )?;
+    }
+    writeln!(out, "</table>\n</body>\n</html>")?;
+
+    Ok(())
+}
+
+/// The rule all the findings are reported under in the SARIF output.
+const SARIF_RULE_ID: &str = "dangling-dns-takeover";
+
+/// A SARIF 2.1.0 log with a result for each of the domains that might be
+/// vulnerable, for tools that collect the findings of several scanners.
+fn sarif<'a>(results: impl Iterator<Item = &'a (String, CheckOutcome)>) -> serde_json::Value {
+    let results: Vec<serde_json::Value> = results
+        .filter(|(_, outcome)| outcome.result == LookupResult::MaybeVulnerable)
+        .map(|(domain, outcome)| {
+            let level = match outcome.severity {
+                Some(Severity::High) => "error",
+                Some(Severity::Medium) => "warning",
+                Some(Severity::Low) | None => "note",
+            };
+            serde_json::json!({
+                "ruleId": SARIF_RULE_ID,
+                "level": level,
+                "message": {
+                    "text": format!("{} : {}", domain, outcome),
+                },
+                "locations": [{
+                    "logicalLocations": [{
+                        "name": domain,
+                        "fullyQualifiedName": domain,
+                        "kind": "resource",
+                    }],
+                }],
+                "properties": outcome,
+            })
+        })
+        .collect();
+
+    serde_json::json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "nsdetect",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": [{
+                        "id": SARIF_RULE_ID,
+                        "name": "DanglingDnsTakeover",
+                        "shortDescription": {
+                            "text": "The domain might be taken over through a dangling DNS record",
+                        },
+                        "fullDescription": {
+                            "text": "The domain got a SERVFAIL answer, is delegated to a lame name server or one in an unregistered domain, or has a CNAME chain that ends at a name that can be claimed by someone else.",
+                        },
+                    }],
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[derive(Serialize)]
+struct NdjsonLine<'a> {
+    domain: &'a str,
+    #[serde(flatten)]
+    outcome: &'a CheckOutcome,
+}
+
+fn print(
+    out: &mut dyn WriteColor,
+    domain: &str,
+    outcome: &CheckOutcome,
+    format: OutputFormat,
+) -> Result<()> {
+    match format {
+        OutputFormat::Ndjson => {
+            let line = NdjsonLine { domain, outcome };
+            writeln!(out, "{}", serde_json::to_string(&line)?)?;
+            return Ok(());
+        }
+        OutputFormat::Csv => {
+            let result = outcome.result.to_string();
+            let severity = outcome
+                .severity
+                .map(|severity| severity.to_string())
+                .unwrap_or_default();
+            let code = outcome
+                .response_code
+                .map(|code| format!("{:?}", code))
+                .unwrap_or_default();
+            let provider = outcome.provider.as_deref().unwrap_or_default();
+            let dangling_cname = outcome.dangling_cname.as_deref().unwrap_or_default();
+            let lame_name_server = outcome
+                .lame_name_server
+                .as_ref()
+                .map(|lame| lame.name.as_str())
+                .unwrap_or_default();
+            let unregistered_ns_domain = outcome
+                .unregistered_ns_domain
+                .as_deref()
+                .unwrap_or_default();
+            let ips: Vec<String> = outcome.ips.iter().map(|ip| ip.to_string()).collect();
+            let duration_ms = outcome
+                .duration_ms
+                .map(|ms| ms.to_string())
+                .unwrap_or_default();
+            return write_csv_record(
+                out,
+                &[
+                    domain,
+                    &result,
+                    &severity,
+                    &code,
+                    provider,
+                    dangling_cname,
+                    lame_name_server,
+                    unregistered_ns_domain,
+                    &ips.join(" "),
+                    &duration_ms,
+                ],
+            );
+        }
+        OutputFormat::Markdown => {
+            writeln!(
+                out,
+                "| {} | {} | {} |",
+                domain.replace('|', "\\|"),
+                outcome.result,
+                outcome
+                    .severity
+                    .map(|severity| severity.to_string())
+                    .unwrap_or_default()
+            )?;
+            return Ok(());
+        }
+        OutputFormat::Text | OutputFormat::Json | OutputFormat::Html | OutputFormat::Sarif => {}
+    }
+
+    write!(out, "{} : ", domain)?;
+
+    match outcome.result {
+        LookupResult::MaybeVulnerable => {
+            out.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?
+        }
+        LookupResult::Safe => out.set_color(ColorSpec::new().set_fg(Some(Color::Green)))?,
+        LookupResult::LookupError | LookupResult::TimedOut => {
+            out.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?
+        }
+        LookupResult::InvalidInput => {
+            out.set_color(ColorSpec::new().set_fg(Some(Color::Magenta)))?
+        }
+        LookupResult::Inconsistent => out.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))?,
+    };
+    write!(out, "{}", outcome)?;
+    out.reset()?;
+    writeln!(out)?;
+
+    Ok(())
+}
+
+/// The CNAME chains of the scanned domains, as the edges between the names.
+#[derive(Default)]
+struct Graph {
+    domains: IndexSet<String>,
+    edges: IndexSet<(String, String)>,
+    /// The names at the end of a chain that don't resolve, or belong to a
+    /// provider where they can be claimed
+    dangling: HashSet<String>,
+}
+
+impl Graph {
+    fn add(&mut self, domain: &str, outcome: &CheckOutcome) {
+        let Some(target) = outcome.cname_chain.last() else {
+            return;
+        };
+        self.domains.insert(domain.to_string());
+        let mut from = domain;
+        for name in &outcome.cname_chain {
+            self.edges.insert((from.to_string(), name.clone()));
+            from = name;
+        }
+        if outcome.result == LookupResult::MaybeVulnerable
+            && (outcome.dangling_cname.is_some() || outcome.provider.is_some())
+        {
+            self.dangling.insert(target.clone());
+        }
+    }
+
+    /// Writes the graph in the DOT format of Graphviz, to be rendered with `dot`.
+    fn write(&self, path: &Path) -> Result<()> {
+        let mut dot = String::from("digraph cnames {\n    rankdir=LR;\n");
+        for domain in &self.domains {
+            dot.push_str(&format!("    {} [shape=box];\n", dot_id(domain)));
+        }
+        for name in &self.dangling {
+            dot.push_str(&format!(
+                "    {} [color=red, fontcolor=red];\n",
+                dot_id(name)
+            ));
+        }
+        for (from, to) in &self.edges {
+            let color = if self.dangling.contains(to) {
+                " [color=red]"
+            } else {
+                ""
+            };
+            dot.push_str(&format!(
+                "    {} -> {}{};\n",
+                dot_id(from),
+                dot_id(to),
+                color
+            ));
+        }
+        dot.push_str("}\n");
+
+        std::fs::write(path, dot)
+            .with_context(|| format!("failed to write the graph to {}", path.display()))
+    }
+}
+
+/// A name as a quoted DOT identifier.
+fn dot_id(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The parts of a json report that are needed to compare it to another one.
+#[derive(Deserialize)]
+struct SavedReport {
+    results: IndexMap<String, SavedOutcome>,
+}
+
+#[derive(Deserialize)]
+struct SavedOutcome {
+    result: LookupResult,
+}
+
+impl SavedReport {
+    fn load(path: &Path) -> Result<SavedReport> {
+        let file = File::open(path)
+            .with_context(|| format!("failed to read result file {}", path.display()))?;
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("invalid result file {}", path.display()))
+    }
+}
+
+#[derive(Serialize)]
+struct Change<'a> {
+    domain: &'a str,
+    old: LookupResult,
+    new: LookupResult,
+}
+
+#[derive(Serialize)]
+struct Presence<'a> {
+    domain: &'a str,
+    result: LookupResult,
+}
+
+/// The differences between two reports.
+#[derive(Serialize)]
+struct Diff<'a> {
+    changed: Vec<Change<'a>>,
+    added: Vec<Presence<'a>>,
+    removed: Vec<Presence<'a>>,
+}
+
+impl<'a> Diff<'a> {
+    fn new(old: &'a SavedReport, new: &'a SavedReport) -> Diff<'a> {
+        let mut diff = Diff {
+            changed: vec![],
+            added: vec![],
+            removed: vec![],
+        };
+        for (domain, outcome) in &new.results {
+            match old.results.get(domain) {
+                Some(previous) if previous.result != outcome.result => diff.changed.push(Change {
+                    domain,
+                    old: previous.result,
+                    new: outcome.result,
+                }),
+                Some(_) => {}
+                None => diff.added.push(Presence {
+                    domain,
+                    result: outcome.result,
+                }),
+            }
+        }
+        for (domain, outcome) in &old.results {
+            if !new.results.contains_key(domain) {
+                diff.removed.push(Presence {
+                    domain,
+                    result: outcome.result,
+                });
+            }
+        }
+        diff
+    }
+}
+
+/// Prints the domains that changed between two saved json reports.
+fn diff(
+    old: &Path,
+    new: &Path,
+    output_path: &Option<PathBuf>,
+    format: OutputFormat,
+) -> Result<ExitCode> {
+    let old = SavedReport::load(old)?;
+    let new = SavedReport::load(new)?;
+    let diff = Diff::new(&old, &new);
+
+    let mut out = output(output_path, false)?;
+    match format {
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&diff)?)?,
+        OutputFormat::Text => {
+            for change in &diff.changed {
+                writeln!(
+                    out,
+                    "~ {} : {} -> {}",
+                    change.domain, change.old, change.new
+                )?;
+            }
+            for added in &diff.added {
+                writeln!(out, "+ {} : {}", added.domain, added.result)?;
+            }
+            for removed in &diff.removed {
+                writeln!(out, "- {} : {}", removed.domain, removed.result)?;
+            }
+        }
+        OutputFormat::Ndjson
+        | OutputFormat::Csv
+        | OutputFormat::Html
+        | OutputFormat::Markdown
+        | OutputFormat::Sarif => {
+            return Err(anyhow!("diff can only print text or json"));
+        }
+    }
+    out.flush()
+        .context("failed to write the results to the output")?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+#[derive(Serialize)]
+struct DryRun<'a> {
+    domains: &'a [String],
+    /// The entries that would be reported as InvalidInput without a lookup
+    invalid: &'a [String],
+    count: usize,
+}
+
+/// Prints the domains a scan would look up, without looking them up.
+fn dry_run(args: &Args, format: OutputFormat) -> Result<ExitCode> {
+    let mut domains = vec![];
+    let mut invalid = vec![];
+    for domain in filter_input(args, input(args)?.0) {
+        let domain = domain?;
+        if ascii_domain(split_name_server(&domain).0).is_some() {
+            domains.push(domain);
+        } else {
+            invalid.push(domain);
+        }
+    }
+
+    let mut out = output(&args.output, use_color(args))?;
+    match format {
+        OutputFormat::Json => {
+            let dry_run = DryRun {
+                domains: &domains,
+                invalid: &invalid,
+                count: domains.len(),
+            };
+            writeln!(out, "{}", serde_json::to_string_pretty(&dry_run)?)?;
+        }
+        OutputFormat::Ndjson => {
+            for domain in &domains {
+                writeln!(out, "{}", serde_json::json!({ "domain": domain }))?;
+            }
+        }
+        OutputFormat::Csv => {
+            write_csv_record(&mut *out, &["domain"])?;
+            for domain in &domains {
+                write_csv_record(&mut *out, &[domain])?;
+            }
+        }
+        OutputFormat::Markdown => {
+            writeln!(out, "| Domain |\n| --- |")?;
+            for domain in &domains {
+                writeln!(out, "| {} |", domain.replace('|', "\\|"))?;
+            }
+        }
+        OutputFormat::Text => {
+            for domain in &domains {
+                writeln!(out, "{}", domain)?;
+            }
+        }
+        OutputFormat::Html | OutputFormat::Sarif => {
+            return Err(anyhow!(
+                "--dry-run can't print {}, use text, json, ndjson, csv or markdown",
+                format
+            ));
+        }
+    }
+    out.flush()
+        .context("failed to write the domains to the output")?;
+
+    if !args.quiet {
+        for domain in &invalid {
+            eprintln!(
+                "warning: \"{}\" isn't a valid domain and would be reported as InvalidInput",
+                domain
+            );
+        }
+        eprintln!("Domains to look up: {}", domains.len());
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Skips blank lines, comments and domains that were already seen, and stops
+/// at the first invalid domain in strict mode.
+fn filter_input(args: &Args, input: Domains) -> Domains {
+    let mut seen = HashSet::new();
+    let no_dedup = args.no_dedup;
+    let strict = args.strict;
+    Box::new(
+        input
+            .filter_map(|line| match line {
+                Ok(line) => input_domain(&line).map(Ok),
+                Err(err) => Some(Err(err)),
+            })
+            .filter(move |domain| match domain {
+                Ok(domain) => no_dedup || seen.insert(domain.clone()),
+                Err(_) => true,
+            })
+            .map(move |domain| match domain {
+                Ok(domain) if strict && ascii_domain(split_name_server(&domain).0).is_none() => {
+                    Err(anyhow!("invalid domain \"{}\" in the input", domain))
+                }
+                domain => domain,
+            }),
+    )
+}
+
+/// Checks the domains in `input`, in async mode if it's asked for. With
+/// --cache the domains with a fresh result in the cache aren't looked up,
+/// unless `use_cached` is false, and the cache is updated with the ones that
+/// were.
+fn scan(
+    args: &Args,
+    input: Domains,
+    config: ResolverConfig,
+    opts: ResolverOpts,
+    options: &InspectOptions,
+    use_cached: bool,
+    mut on_result: impl FnMut(usize, String, CheckOutcome, SystemTime) -> Result<()>,
+) -> Result<()> {
+    let cache = match &args.cache {
+        Some(path) => Some(Arc::new(Cache::load(path, args.cache_ttl)?)),
+        None => None,
+    };
+    let cached = cache.clone();
+    let resumed = match &args.resume {
+        Some(path) => Some(Resumed::load(path)?),
+        None => None,
+    };
+    // the appended output already has the resumed results in it
+    let append = args.append;
+    let mut to_check: Pending = Box::new(filter_input(args, input).filter_map(move |domain| {
+        let domain = match domain {
+            Ok(domain) => domain,
+            Err(err) => return Some(Err(err)),
+        };
+        let outcome = match resumed.as_ref().and_then(|resumed| resumed.get(&domain)) {
+            Some(_) if append => return None,
+            Some(outcome) => Some(outcome),
+            None => cached
+                .as_ref()
+                .filter(|_| use_cached)
+                .and_then(|cache| cache.get(&domain))
+                .map(|entry| entry.outcome.clone()),
+        };
+        Some(Ok((domain, outcome)))
+    }));
+
+    // the position in the input of each domain, in the order they're looked up
+    let mut input_order = None;
+    if args.shuffle {
+        let mut pending: Vec<_> = to_check.enumerate().collect();
+        match args.seed {
+            Some(seed) => pending.shuffle(&mut StdRng::seed_from_u64(seed)),
+            None => pending.shuffle(&mut rand::thread_rng()),
+        }
+        let (order, shuffled): (Vec<usize>, Vec<_>) = pending.into_iter().unzip();
+        to_check = Box::new(shuffled.into_iter());
+        input_order = Some(order);
+    }
+
+    let allowlist = match &args.allowlist {
+        Some(path) => Some(Allowlist::load(path)?),
+        None => None,
+    };
+
+    let mut checked = HashMap::new();
+    let on_result = |i: usize, domain: String, outcome: CheckOutcome| {
+        let i = input_order.as_ref().map_or(i, |order| order[i]);
+        let cached_at = cache
+            .as_ref()
+            .filter(|_| use_cached)
+            .and_then(|cache| cache.get(&domain))
+            .map(|entry| UNIX_EPOCH + Duration::from_secs(entry.checked_at));
+        let checked_at = cached_at.unwrap_or_else(SystemTime::now);
+        if cache.is_some() && cached_at.is_none() && Cache::keeps(&outcome) {
+            checked.insert(
+                domain.clone(),
+                CacheEntry {
+                    checked_at: unix_time(checked_at)?,
+                    outcome: outcome.clone(),
+                },
+            );
+        }
+        let outcome = match &allowlist {
+            Some(allowlist) => allowlist.suppress(&domain, outcome),
+            None => outcome,
+        };
+        info!(domain, result = %outcome.result, "checked");
+        on_result(i, domain, outcome, checked_at)
+    };
+
+    if args.r#async {
+        check_async(to_check, config, opts, options, args, on_result)?;
+    } else {
+        check(to_check, config, opts, options, args, on_result)?;
+    }
+
+    match (cache, &args.cache) {
+        (Some(cache), Some(path)) => cache.save(path, checked),
+        _ => Ok(()),
+    }
+}
+
+/// The results of an earlier scan that was interrupted, loaded from a json
+/// report or from ndjson lines.
+struct Resumed {
+    results: HashMap<String, CheckOutcome>,
+}
+
+#[derive(Deserialize)]
+struct ResumedReport {
+    results: HashMap<String, CheckOutcome>,
+}
+
+#[derive(Deserialize)]
+struct ResumedLine {
+    domain: String,
+    #[serde(flatten)]
+    outcome: CheckOutcome,
+}
+
+impl Resumed {
+    fn load(path: &Path) -> Result<Resumed> {
+        // the same command can start the scan and resume it
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read result file {}", path.display()))
+            }
+        };
+        if let Ok(report) = serde_json::from_str::<ResumedReport>(&text) {
+            return Ok(Resumed {
+                results: report.results,
+            });
+        }
+
+        // the last line is cut short if the scan crashed while writing it
+        let lines: Vec<(usize, &str)> = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .collect();
+        let mut results = HashMap::new();
+        for (n, &(i, line)) in lines.iter().enumerate() {
+            match serde_json::from_str::<ResumedLine>(line) {
+                Ok(line) => {
+                    results.insert(line.domain, line.outcome);
+                }
+                Err(_) if n + 1 == lines.len() => {
+                    debug!(path = %path.display(), "skipping the incomplete last line");
+                }
+                Err(err) => {
+                    return Err(err).with_context(|| {
+                        format!("invalid result on line {} of {}", i + 1, path.display())
+                    })
+                }
+            }
+        }
+        debug!(path = %path.display(), results = results.len(), "resuming the scan");
+        Ok(Resumed { results })
+    }
+
+    /// The earlier outcome of `domain`, unless it wasn't completed.
+    fn get(&self, domain: &str) -> Option<CheckOutcome> {
+        self.results
+            .get(domain)
+            .filter(|outcome| outcome.result != LookupResult::TimedOut)
+            .cloned()
+    }
+}
+
+/// Domains whose findings are known exceptions, by name or by a `*.` pattern
+/// that matches all the subdomains of a domain.
+struct Allowlist {
+    names: HashSet<String>,
+    suffixes: Vec<String>,
+}
+
+impl Allowlist {
+    fn load(path: &Path) -> Result<Allowlist> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read allowlist {}", path.display()))?;
+
+        let mut allowlist = Allowlist {
+            names: HashSet::new(),
+            suffixes: vec![],
+        };
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.strip_prefix("*.") {
+                Some(domain) => allowlist
+                    .suffixes
+                    .push(format!(".{}", normalize_domain(domain))),
+                None => {
+                    allowlist.names.insert(normalize_domain(line));
+                }
+            }
+        }
+        Ok(allowlist)
+    }
+
+    fn contains(&self, domain: &str) -> bool {
+        self.names.contains(domain) || self.suffixes.iter().any(|suffix| domain.ends_with(suffix))
+    }
+
+    /// Reports a domain that might be vulnerable as Safe if it's in the
+    /// allowlist, whatever name server it was looked up with.
+    fn suppress(&self, domain: &str, outcome: CheckOutcome) -> CheckOutcome {
+        let (name, _) = split_name_server(domain);
+        if outcome.result != LookupResult::MaybeVulnerable || !self.contains(name) {
+            return outcome;
+        }
+        debug!(domain, "suppressed by the allowlist");
+        CheckOutcome {
+            result: LookupResult::Safe,
+            severity: None,
+            suppressed: true,
+            ..outcome
+        }
+    }
+}
+
+/// Results of earlier scans, stored as json, so that domains that were
+/// checked recently don't have to be looked up again.
+struct Cache {
+    entries: HashMap<String, CacheEntry>,
+    /// Entries checked before this are stale
+    fresh_after: u64,
+}
+
+/// `time` in seconds since the unix epoch.
+fn unix_time(time: SystemTime) -> Result<u64> {
+    Ok(time
+        .duration_since(UNIX_EPOCH)
+        .context("the clock is set before 1970")?
+        .as_secs())
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    /// When the domain was looked up, in seconds since the unix epoch
+    checked_at: u64,
+    #[serde(flatten)]
+    outcome: CheckOutcome,
+}
+
+impl Cache {
+    fn load(path: &Path, ttl: Duration) -> Result<Cache> {
+        let entries = match File::open(path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))
+                .with_context(|| format!("invalid cache file {}", path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read cache file {}", path.display()))
+            }
+        };
+        let now = unix_time(SystemTime::now())?;
+
+        Ok(Cache {
+            entries,
+            fresh_after: now.saturating_sub(ttl.as_secs()),
+        })
+    }
+
+    /// If `outcome` is kept in the cache. Failed lookups and the ones that
+    /// timed out are checked again the next time instead.
+    fn keeps(outcome: &CheckOutcome) -> bool {
+        !matches!(
+            outcome.result,
+            LookupResult::LookupError | LookupResult::TimedOut
+        )
+    }
+
+    /// The cached entry for `domain`, if it's still fresh.
+    fn get(&self, domain: &str) -> Option<&CacheEntry> {
+        self.entries
+            .get(domain)
+            .filter(|entry| entry.checked_at >= self.fresh_after && Cache::keeps(&entry.outcome))
+    }
+
+    /// Writes the cache back to `path`, with the entries of the domains that were just checked.
+    fn save(&self, path: &Path, checked: HashMap<String, CacheEntry>) -> Result<()> {
+        let mut entries = self.entries.clone();
+        entries.extend(checked);
+
+        let file = File::create(path)
+            .with_context(|| format!("failed to create cache file {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, &entries)?;
+        writer
+            .flush()
+            .with_context(|| format!("failed to write cache file {}", path.display()))
+    }
+}
+
+/// Checks the domains again every `interval`, and prints the ones whose
+/// result changed since the previous round, which is all of them the first
+/// time. Stdin can only be read once, so it's kept in memory in between.
+fn watch(
+    args: &Args,
+    config: ResolverConfig,
+    opts: ResolverOpts,
+    options: &InspectOptions,
+    format: OutputFormat,
+    filter: Option<Filter>,
+    interval: Duration,
+) -> Result<ExitCode> {
+    if !format.is_streamable() {
+        return Err(anyhow!(
+            "--watch can't be used with --format {}, use --format ndjson instead",
+            format
+        ));
+    }
+
+    let from_stdin = args.input_file.is_empty() && args.domain.is_empty();
+    let stdin: Vec<String> = if from_stdin {
+        input(args)?.0.collect::<Result<_>>()?
+    } else {
+        vec![]
+    };
+
+    let mut out = output(&args.output, use_color(args))?;
+    print_header(&mut *out, format)?;
+    let error_kinds = args.only_errors.as_deref().unwrap_or_default();
+    let mut previous: HashMap<String, LookupResult> = HashMap::new();
+    // the cache only saves lookups in the first round, after that the
+    // domains are looked up again every round to see if they changed
+    let mut first_round = true;
+    loop {
+        let input: Domains = if from_stdin {
+            Box::new(stdin.clone().into_iter().map(Ok))
+        } else {
+            input(args)?.0
+        };
+
+        let mut summary = Summary::default();
+        let mut current = HashMap::new();
+        let start = Instant::now();
+        scan(
+            args,
+            input,
+            config.clone(),
+            opts,
+            options,
+            first_round,
+            |_, domain, outcome, _| {
+                summary.add(&outcome.result);
+                if previous.get(&domain) != Some(&outcome.result)
+                    && Filter::matches(filter, error_kinds, &outcome)
+                {
+                    print(&mut *out, &domain, &outcome, format)?;
+                }
+                current.insert(domain, outcome.result);
+                Ok(())
+            },
+        )?;
+        out.flush()
+            .context("failed to write the results to the output")?;
+        if !args.quiet {
+            eprintln!("{}", summary);
+        }
+        if let Some(path) = &args.metrics {
+            summary.write_metrics(path, start.elapsed())?;
+        }
+
+        previous = current;
+        first_round = false;
+
+        // wake up now and then so that Ctrl-C doesn't have to wait for the
+        // whole interval
+        let next_round = Instant::now() + interval;
+        while !STOP.load(Ordering::SeqCst) && Instant::now() < next_round {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        if STOP.load(Ordering::SeqCst) {
+            return Ok(ExitCode::SUCCESS);
+        }
+    }
+}
+
+/// Prints the completion script of nsdetect for `shell`.
+fn completions(shell: Shell) -> Result<ExitCode> {
+    let mut script = vec![];
+    clap_complete::generate(
+        shell,
+        &mut Cli::command(),
+        env!("CARGO_PKG_NAME"),
+        &mut script,
+    );
+    std::io::stdout()
+        .write_all(&script)
+        .context("failed to write the completion script")?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Prints the built-in fingerprints, one `service : cname suffix` per line.
+fn list_fingerprints() -> Result<ExitCode> {
+    let mut out = std::io::stdout().lock();
+    for fingerprint in default_fingerprints() {
+        writeln!(
+            out,
+            "{} : {}",
+            fingerprint.service, fingerprint.cname_suffix
+        )?;
+    }
+    out.flush()
+        .context("failed to write the fingerprints to stdout")?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Options that replace each other as a whole, so that giving one of them on
+/// the command line leaves out the others in the --config file as well.
+const OVERRIDE_GROUPS: &[&[&str]] = &[&["input_file", "domain"]];
+
+/// The options in the --config file as command line arguments, leaving out
+/// the ones that are already given on the command line or in the
+/// environment, or that conflict with one that is.
+fn config_args(
+    path: &Path,
+    command: &clap::Command,
+    matches: &ArgMatches,
+) -> Result<Vec<OsString>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read the config file {}", path.display()))?;
+    let table: toml::Table = toml::from_str(&content)
+        .with_context(|| format!("failed to parse the config file {}", path.display()))?;
+
+    let mut config_args = vec![];
+    for (key, value) in table {
+        let id = key.replace('-', "_");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == id.as_str() && arg.get_long().is_some() && id != "config")
+            .ok_or_else(|| anyhow!("unknown option {:?} in {}", key, path.display()))?;
+        // an option given otherwise also replaces the ones it conflicts with
+        let given = |id: &str| {
+            matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        };
+        let overridden = OVERRIDE_GROUPS
+            .iter()
+            .filter(|group| group.contains(&id.as_str()))
+            .any(|group| group.iter().any(|other| given(other)));
+        if given(&id)
+            || overridden
+            || command
+                .get_arg_conflicts_with(arg)
+                .iter()
+                .any(|other| given(other.get_id().as_str()))
+        {
+            continue;
+        }
+
+        let flag = format!(
+            "--{}",
+            arg.get_long().expect("only long options are looked up")
+        );
+        let values = match value {
+            toml::Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            match (arg.get_action(), value) {
+                (ArgAction::SetTrue, toml::Value::Boolean(set)) => {
+                    if set {
+                        config_args.push(OsString::from(&flag));
+                    }
+                }
+                (ArgAction::Count, toml::Value::Integer(count)) => {
+                    for _ in 0..count {
+                        config_args.push(OsString::from(&flag));
+                    }
+                }
+                (ArgAction::SetTrue | ArgAction::Count, value) => {
+                    return Err(anyhow!(
+                        "invalid value {} for {:?} in {}",
+                        value,
+                        key,
+                        path.display()
+                    ));
+                }
+                (_, value) => {
+                    // strings are passed on without the quotes they have in toml
+                    let value = match value {
+                        toml::Value::String(value) => value,
+                        value => value.to_string(),
+                    };
+                    config_args.push(OsString::from(format!("{}={}", flag, value)))
+                }
+            }
+        }
+    }
+    Ok(config_args)
+}
+
+/// Parses the command line, on top of the options in the --config file if
+/// one is given, so that the options from the command line win.
+fn parse_cli() -> Result<Cli> {
+    let mut argv: Vec<OsString> = std::env::args_os().collect();
+    let command = Cli::command();
+    let matches = command.clone().get_matches_from(&argv);
+    // the options of the scan subcommand come after it, and it comes first
+    let (command, scan_matches, at) = match matches.subcommand() {
+        Some(("scan", scan_matches)) => (
+            command
+                .find_subcommand("scan")
+                .expect("scan is a subcommand")
+                .clone(),
+            scan_matches,
+            2,
+        ),
+        Some(_) => return Ok(Cli::from_arg_matches(&matches)?),
+        None => (command, &matches, 1),
+    };
+    let Some(path) = scan_matches.get_one::<PathBuf>("config") else {
+        return Ok(Cli::from_arg_matches(&matches)?);
+    };
+
+    let config_args = config_args(path, &command, scan_matches)?;
+    argv.splice(at..at, config_args);
+    let matches = Cli::command().get_matches_from(&argv);
+    Ok(Cli::from_arg_matches(&matches)?)
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(err) => {
+            // the causes on the same line, instead of the multi-line report
+            eprintln!("error: {:#}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<ExitCode> {
+    let cli = parse_cli()?;
+    let args = match cli.command {
+        Some(Command::Scan(args)) => *args,
+        Some(Command::Diff(diff_args)) => {
+            return diff(
+                &diff_args.old,
+                &diff_args.new,
+                &diff_args.output,
+                diff_args.format,
+            );
+        }
+        Some(Command::Completions { shell }) => return completions(shell),
+        Some(Command::Fingerprints {
+            command: FingerprintsCommand::List,
+        }) => return list_fingerprints(),
+        None => cli.args,
+    };
+    init_logging(args.verbose, args.log_format);
+
+    if let Some(shell) = args.generate_completions {
+        return completions(shell);
+    }
+
+    if !args.diff.is_empty() {
+        return diff(
+            &args.diff[0],
+            &args.diff[1],
+            &args.output,
+            output_format(&args),
+        );
+    }
+
+    if !args.input_file.is_empty() && !args.domain.is_empty() {
+        return Err(anyhow!(
+            "the --input_file option and the --domain option are mutually exclusive"
+        ));
+    }
+
+    if args.dry_run {
+        return dry_run(&args, output_format(&args));
+    }
+
+    let (config, opts) = resolver_config(&args)?;
+    stop_on_ctrl_c()?;
+
+    let mut options = InspectOptions {
+        http_probe: args.http_probe,
+        treat_as_vulnerable: args.treat_as_vulnerable.clone(),
+        record_type: args.record_type.map(RecordType::from),
+        // the name servers are asked over the same transport and from the
+        // same address as the resolver, on the standard port
+        lame_delegation: args.lame_delegation.then_some(DirectOptions {
+            bind: args.bind,
+            tcp: args.tcp,
+            timeout: opts.timeout,
+        }),
+        unregistered_ns: args.unregistered_ns,
+        servfail_retries: args.servfail_retries,
+        // a response that wasn't recorded fails with an io error every time
+        io_retries: if args.replay.is_some() {
+            0
+        } else {
+            args.io_retries
+        },
+        cname_chain: args.graph.is_some(),
+        max_cname_depth: args.max_cname_depth,
+        ip_versions: args.both_ip_versions,
+        ..InspectOptions::default()
+    };
+
+    if let Some(path) = &args.fingerprints {
+        let fingerprints = load_fingerprints(path)?;
+        if args.replace_fingerprints {
+            options.fingerprints = fingerprints;
+        } else {
+            options.fingerprints.extend(fingerprints);
+        }
+    }
+
+    if let Some(path) = &args.psl {
+        load_public_suffix_list(path)?;
+    }
+
+    let format = output_format(&args);
+    let filter = if args.only_vulnerable {
+        Some(Filter::Vulnerable)
+    } else if args.only_errors.is_some() {
+        Some(Filter::Failed)
+    } else if args.quiet && args.filter.is_none() {
+        Some(Filter::Problems)
+    } else {
+        args.filter
+    };
+    let error_kinds = args.only_errors.as_deref().unwrap_or_default();
+
+    if args.compare && compared_name_servers(&args).len() < 2 {
+        return Err(anyhow!("--compare needs at least two name servers"));
+    }
+
+    if args.append && format != OutputFormat::Ndjson {
+        return Err(anyhow!("--append only works with --format ndjson"));
+    }
+
+    if let Some(interval) = args.watch {
+        return watch(&args, config, opts, &options, format, filter, interval);
+    }
+
+    let start = Instant::now();
+    let resolver = resolver_description(&args, &config);
+    let (input, source) = input(&args)?;
+    let len = input.size_hint().1;
+
+    // results are printed as they complete, unless they have to be ordered
+    // first or go into a single document
+    let buffered =
+        !format.is_streamable() || args.sort || args.shuffle || args.r#async && args.ordered;
+    let streaming = !buffered;
+    let mut out = match &args.output {
+        Some(path) if args.append => append_output(path)?,
+        _ => output(&args.output, use_color(&args))?,
+    };
+    print_header(&mut *out, format)?;
+    let mut summary = Summary {
+        source,
+        ..Summary::default()
+    };
+    // not pre-sized, the number of domains isn't known for streamed input, and
+    // growing the Vec is negligible next to the lookups even for 100k domains
+    let mut results = vec![];
+    // when each domain was checked, for the html report
+    let mut checked_at = HashMap::new();
+    let mut graph = Graph::default();
+    let progress = progress_bar(&args, format, len)?;
+    let on_result = |i: usize, domain: String, outcome: CheckOutcome, at: SystemTime| {
+        summary.add(&outcome.result);
+        progress.inc(1);
+        if args.graph.is_some() {
+            graph.add(&domain, &outcome);
+        }
+        if streaming {
+            if Filter::matches(filter, error_kinds, &outcome) {
+                progress.suspend(|| print(&mut *out, &domain, &outcome, format))?;
+            }
+        } else {
+            if format == OutputFormat::Html {
+                checked_at.insert(domain.clone(), at);
+            }
+            results.push((i, domain, outcome));
+        }
+        Ok(())
+    };
+
+    scan(&args, input, config, opts, &options, true, on_result)?;
+
+    results.sort_by_key(|(i, _, _)| *i);
+    let mut results: Vec<_> = results
+        .into_iter()
+        .map(|(_, domain, outcome)| (domain, outcome))
+        .collect();
+    if args.sort {
+        sort_results(&mut results);
+    }
+
+    let code = if args.no_fail {
+        ExitCode::SUCCESS
+    } else {
+        summary.exit_code(args.fail_threshold)
+    };
+
+    if !streaming {
+        print_results(
+            &mut *out,
+            &results,
+            &summary,
+            &resolver,
+            &checked_at,
+            format,
+            filter,
+            error_kinds,
+        )?;
+    }
+    out.flush()
+        .context("failed to write the results to the output")?;
+    progress.finish_and_clear();
+    if !args.quiet {
+        eprintln!("{}", summary);
+    }
+    if let Some(path) = &args.metrics {
+        summary.write_metrics(path, start.elapsed())?;
+    }
+    if let Some(path) = &args.graph {
+        graph.write(path)?;
+    }
+
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file of its own for a test, that is removed again when it's dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, content: &str) -> TempFile {
+            let path = std::env::temp_dir().join(format!(
+                "nsdetect-{}-{}-{:?}",
+                name,
+                std::process::id(),
+                std::thread::current().id()
+            ));
+            std::fs::write(&path, content).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn outcome(json: &str) -> CheckOutcome {
+        serde_json::from_str(json).unwrap()
+    }
+
+    /// The arguments of a scan with `argv` on the command line.
+    fn args(argv: &[&str]) -> Args {
+        Cli::parse_from(std::iter::once("nsdetect").chain(argv.iter().copied())).args
+    }
+
+    /// The arguments the --config file with `config` in it adds to a scan
+    /// with `argv` on the command line.
+    fn config_args_of(config: &str, argv: &[&str]) -> Result<Vec<String>> {
+        let file = TempFile::new("config", config);
+        let command = Cli::command();
+        let matches = command
+            .clone()
+            .get_matches_from(std::iter::once("nsdetect").chain(argv.iter().copied()));
+        let mut args: Vec<String> = config_args(&file.0, &command, &matches)?
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect();
+        args.sort();
+        Ok(args)
+    }
+
+    /// The protocol and port of each name server in the configuration for `ns`.
+    fn entry_servers(argv: &[&str], ns: &str) -> Vec<(Protocol, u16)> {
+        entry_config(&args(argv), ns)
+            .unwrap()
+            .name_servers()
+            .iter()
+            .map(|ns| (ns.protocol, ns.socket_addr.port()))
+            .collect()
+    }
+
+    #[test]
+    fn cache_skips_stale_and_failed_outcomes() {
+        let now = unix_time(SystemTime::now()).unwrap();
+        let file = TempFile::new(
+            "cache",
+            &format!(
+                r#"{{
+                    "fresh.example": {{"checked_at": {now}, "result": "Safe"}},
+                    "stale.example": {{"checked_at": {old}, "result": "Safe"}},
+                    "failed.example": {{"checked_at": {now}, "result": "LookupError", "error_kind": "io"}},
+                    "late.example": {{"checked_at": {now}, "result": "TimedOut"}}
+                }}"#,
+                now = now,
+                old = now - 7200
+            ),
+        );
+
+        let cache = Cache::load(&file.0, Duration::from_secs(3600)).unwrap();
+        assert_eq!(
+            cache.get("fresh.example").map(|entry| entry.outcome.result),
+            Some(LookupResult::Safe)
+        );
+        assert!(cache.get("stale.example").is_none());
+        assert!(cache.get("failed.example").is_none());
+        assert!(cache.get("late.example").is_none());
+        assert!(cache.get("unknown.example").is_none());
+    }
+
+    #[test]
+    fn cache_keeps_the_time_each_domain_was_checked() {
+        let file = TempFile::new("cache-save", "{}");
+        let cache = Cache::load(&file.0, Duration::from_secs(3600)).unwrap();
+        let checked = HashMap::from([(
+            "www.example".to_string(),
+            CacheEntry {
+                checked_at: 1234,
+                outcome: outcome(r#"{"result": "Safe"}"#),
+            },
+        )]);
+        cache.save(&file.0, checked).unwrap();
+
+        let saved: HashMap<String, serde_json::Value> =
+            serde_json::from_str(&std::fs::read_to_string(&file.0).unwrap()).unwrap();
+        assert_eq!(saved["www.example"]["checked_at"], 1234);
+        assert!(Cache::keeps(&outcome(r#"{"result": "MaybeVulnerable"}"#)));
+        assert!(!Cache::keeps(&CheckOutcome::timed_out()));
+        assert!(!Cache::keeps(&CheckOutcome::lookup_error(ErrorKind::Io)));
+    }
+
+    #[test]
+    fn input_entries_are_normalized() {
+        assert_eq!(input_entry(" WWW.Example.com. "), "www.example.com");
+        assert_eq!(
+            input_entry("WWW.Example.com@NS1.Example.net."),
+            "www.example.com@ns1.example.net"
+        );
+        assert_eq!(input_entry("www.example.com@"), "www.example.com@");
+        assert_eq!(
+            input_entry("https://Sub.Example.com:8443/path?q=1"),
+            "sub.example.com"
+        );
+        assert_eq!(input_entry("https://user@example.com/"), "example.com");
+        assert_eq!(
+            split_name_server(&input_entry("www.example.com@192.0.2.53")),
+            ("www.example.com", Some("192.0.2.53"))
+        );
+    }
+
+    #[test]
+    fn entry_name_servers_use_the_transport_of_the_others() {
+        assert_eq!(
+            entry_servers(&[], "192.0.2.53"),
+            [(Protocol::Udp, 53), (Protocol::Tcp, 53)]
+        );
+        assert_eq!(
+            entry_servers(&["--port", "5353"], "192.0.2.53"),
+            [(Protocol::Udp, 5353), (Protocol::Tcp, 5353)]
+        );
+        assert_eq!(
+            entry_servers(&["--tcp"], "192.0.2.53"),
+            [(Protocol::Tcp, 53)]
+        );
+        assert_eq!(
+            entry_servers(&["--doh", "--port", "443"], "8.8.8.8"),
+            [(Protocol::Https, 443)]
+        );
+        assert_eq!(entry_servers(&["--dot"], "1.1.1.1"), [(Protocol::Tls, 853)]);
+        assert_eq!(
+            entry_servers(&["--dot", "--tls-name", "ns.example.net"], "192.0.2.53"),
+            [(Protocol::Tls, 853)]
+        );
+        assert!(entry_config(&args(&["--doh"]), "192.0.2.53").is_err());
+    }
+
+    #[test]
+    fn config_options_become_arguments() {
+        let config = r#"
+            concurrency = 100
+            name-server = "10.0.0.2"
+            async = true
+            quiet = false
+            verbose = 2
+            domain = ["a.example", "b.example"]
+        "#;
+
+        assert_eq!(
+            config_args_of(config, &[]).unwrap(),
+            [
+                "--async",
+                "--concurrency=100",
+                "--domain=a.example",
+                "--domain=b.example",
+                "--name-server=10.0.0.2",
+                "--verbose",
+                "--verbose",
+            ]
+        );
+    }
+
+    #[test]
+    fn command_line_options_win_over_the_config() {
+        assert_eq!(
+            config_args_of("concurrency = 100\nasync = true", &["--concurrency", "5"]).unwrap(),
+            ["--async"]
+        );
+        // an option replaces the ones in the config it conflicts with
+        assert_eq!(
+            config_args_of("dot = true", &["--doh"]).unwrap(),
+            Vec::<String>::new()
+        );
+        // the input can come from a file or from --domain, not both
+        assert_eq!(
+            config_args_of(r#"input-file = ["domains.txt"]"#, &["-d", "www.example"]).unwrap(),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            config_args_of(r#"domain = ["www.example"]"#, &["-i", "domains.txt"]).unwrap(),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn invalid_config_options_are_rejected() {
+        assert!(config_args_of("no-such-option = 1", &[]).is_err());
+        assert!(config_args_of(r#"async = "yes""#, &[]).is_err());
+        assert!(config_args_of(r#"config = "other.toml""#, &[]).is_err());
+        assert!(config_args_of("concurrency = ", &[]).is_err());
+    }
+
+    #[test]
+    fn intervals_are_parsed_with_their_unit() {
+        assert_eq!(parse_interval("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_interval("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_interval("2h"), Ok(Duration::from_secs(2 * 3600)));
+        assert_eq!(parse_interval("86400"), Ok(Duration::from_secs(86400)));
+        assert_eq!(parse_interval("1.5m"), Ok(Duration::from_secs(90)));
+        assert!(parse_interval("1d").is_err());
+        assert!(parse_interval("m").is_err());
+        assert!(parse_interval("0s").is_err());
+    }
+
+    #[test]
+    fn allowlist_suppresses_the_listed_findings() {
+        let file = TempFile::new(
+            "allowlist",
+            "# known exceptions\n\nWWW.Example.com.\n*.example.net\n",
+        );
+        let allowlist = Allowlist::load(&file.0).unwrap();
+
+        assert!(allowlist.contains("www.example.com"));
+        assert!(allowlist.contains("a.b.example.net"));
+        assert!(!allowlist.contains("example.net"));
+        assert!(!allowlist.contains("mail.example.com"));
+
+        let vulnerable = outcome(r#"{"result": "MaybeVulnerable", "severity": "High"}"#);
+        let suppressed = allowlist.suppress("www.example.com", vulnerable.clone());
+        assert_eq!(suppressed.result, LookupResult::Safe);
+        assert_eq!(suppressed.severity, None);
+        assert!(suppressed.suppressed);
+        assert_eq!(
+            allowlist.suppress("mail.example.com", vulnerable.clone()),
+            vulnerable
+        );
+        // the name server a domain is looked up with doesn't matter
+        assert!(
+            allowlist
+                .suppress("www.example.com@10.0.0.53", vulnerable.clone())
+                .suppressed
+        );
+        assert!(
+            allowlist
+                .suppress("a.example.net@ns1.example.org", vulnerable.clone())
+                .suppressed
+        );
+        assert_eq!(
+            allowlist.suppress("mail.example.com@www.example.com", vulnerable.clone()),
+            vulnerable
+        );
+        let failed = outcome(r#"{"result": "LookupError", "error_kind": "timeout"}"#);
+        assert_eq!(
+            allowlist.suppress("www.example.com", failed.clone()),
+            failed
+        );
+    }
+
+    #[test]
+    fn resumed_results_skip_the_unfinished_domains() {
+        let file = TempFile::new(
+            "resumed",
+            concat!(
+                r#"{"domain": "safe.example", "result": "Safe"}"#,
+                "\n",
+                r#"{"domain": "late.example", "result": "TimedOut"}"#,
+                "\n",
+                r#"{"domain": "cut.example", "res"#,
+            ),
+        );
+        let resumed = Resumed::load(&file.0).unwrap();
+        assert_eq!(
+            resumed.get("safe.example").map(|outcome| outcome.result),
+            Some(LookupResult::Safe)
+        );
+        assert!(resumed.get("late.example").is_none());
+        assert!(resumed.get("cut.example").is_none());
+
+        let report = TempFile::new(
+            "resumed-report",
+            r#"{"results": {"www.example": {"result": "MaybeVulnerable"}}}"#,
+        );
+        let resumed = Resumed::load(&report.0).unwrap();
+        assert_eq!(
+            resumed.get("www.example").map(|outcome| outcome.result),
+            Some(LookupResult::MaybeVulnerable)
+        );
+
+        let broken = TempFile::new("resumed-broken", "not json\n{\"domain\": \"x\"}\n");
+        assert!(Resumed::load(&broken.0).is_err());
+
+        let missing = std::env::temp_dir().join("nsdetect-resumed-that-does-not-exist");
+        assert!(Resumed::load(&missing)
+            .unwrap()
+            .get("www.example")
+            .is_none());
     }
 }
@@ -0,0 +1,38 @@
+use anyhow::{anyhow, Context, Result};
+use publicsuffix::{List, Psl};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// The public suffix list nsdetect is built with, from https://publicsuffix.org/list/public_suffix_list.dat
+const PUBLIC_SUFFIX_LIST: &str = include_str!("public_suffix_list.dat");
+
+static LIST: OnceLock<List> = OnceLock::new();
+
+/// Uses the public suffix list in the file at `path` instead of the bundled
+/// one. It has to be loaded before the first domain is checked.
+pub fn load_public_suffix_list(path: &Path) -> Result<()> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let list: List = content
+        .parse()
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+
+    LIST.set(list)
+        .map_err(|_| anyhow!("the public suffix list is already in use"))
+}
+
+/// The part of `name` that can be registered, like example.co.uk for
+/// www.example.co.uk, according to the public suffix list.
+pub fn registrable_domain(name: &str) -> Option<String> {
+    let list = LIST.get_or_init(|| {
+        PUBLIC_SUFFIX_LIST
+            .parse()
+            .expect("the bundled public suffix list is valid")
+    });
+
+    let name = name.strip_suffix('.').unwrap_or(name).to_ascii_lowercase();
+    let domain = list.domain(name.as_bytes())?;
+    std::str::from_utf8(domain.as_bytes())
+        .ok()
+        .map(str::to_string)
+}
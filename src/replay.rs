@@ -0,0 +1,341 @@
+use crate::direct::DirectOptions;
+use crate::lookup::{AsyncDnsLookup, DnsLookup};
+use crate::response_code_by_name;
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::warn;
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::lookup::Lookup;
+use trust_dns_resolver::lookup_ip::LookupIp;
+use trust_dns_resolver::proto::error::ProtoError;
+use trust_dns_resolver::proto::op::{Message, Query};
+use trust_dns_resolver::proto::rr::{Name, Record, RecordType};
+use trust_dns_resolver::proto::serialize::binary::{BinDecodable, BinEncodable};
+
+/// A response as it's stored in the directory of a recording, one json file per query.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Recorded {
+    /// The records of the answer, each in the DNS wire format as hex
+    Records(Vec<String>),
+    /// An answer without any records in it
+    NoRecords {
+        response_code: String,
+    },
+    /// The whole answer of a name server that was queried directly, in the
+    /// DNS wire format as hex
+    Message(String),
+    Timeout,
+    Io(String),
+    Proto(String),
+    Other(String),
+}
+
+impl Recorded {
+    fn new(result: Result<&Lookup, &ResolveError>) -> Recorded {
+        match result {
+            Ok(lookup) => Recorded::Records(
+                lookup
+                    .records()
+                    .iter()
+                    .filter_map(|record| record.to_bytes().ok())
+                    .map(|bytes| encode_hex(&bytes))
+                    .collect(),
+            ),
+            Err(err) => match err.kind() {
+                ResolveErrorKind::NoRecordsFound { response_code, .. } => Recorded::NoRecords {
+                    response_code: format!("{:?}", response_code),
+                },
+                ResolveErrorKind::Timeout => Recorded::Timeout,
+                ResolveErrorKind::Io(err) => Recorded::Io(err.to_string()),
+                ResolveErrorKind::Proto(err) => Recorded::Proto(err.to_string()),
+                _ => Recorded::Other(err.to_string()),
+            },
+        }
+    }
+
+    fn of_message(result: Result<&Message, &ResolveError>) -> Recorded {
+        match result.map(|message| message.to_vec()) {
+            Ok(Ok(bytes)) => Recorded::Message(encode_hex(&bytes)),
+            Ok(Err(err)) => Recorded::Proto(err.to_string()),
+            Err(err) => Recorded::new(Err(err)),
+        }
+    }
+
+    fn into_result(self, query: Query) -> Result<Lookup, ResolveError> {
+        match self {
+            Recorded::Records(records) => {
+                let records = records
+                    .iter()
+                    .map(|hex| decode_record(hex))
+                    .collect::<Result<Vec<Record>, ProtoError>>()?;
+                Ok(Lookup::new_with_max_ttl(query, Arc::from(records)))
+            }
+            Recorded::NoRecords { response_code } => {
+                let response_code = response_code_by_name(&response_code).ok_or_else(|| {
+                    ProtoError::from(format!("unknown response code {:?}", response_code))
+                })?;
+                Err(ResolveErrorKind::NoRecordsFound {
+                    query: Box::new(query),
+                    soa: None,
+                    negative_ttl: None,
+                    response_code,
+                    trusted: true,
+                }
+                .into())
+            }
+            Recorded::Message(_) => Err(ProtoError::from("a direct answer was recorded").into()),
+            failure => Err(failure.into_error()),
+        }
+    }
+
+    fn into_message(self) -> Result<Message, ResolveError> {
+        match self {
+            Recorded::Message(hex) => Ok(Message::from_vec(&decode_hex(&hex)?)?),
+            Recorded::Records(_) | Recorded::NoRecords { .. } => {
+                Err(ProtoError::from("a lookup was recorded instead of a direct answer").into())
+            }
+            failure => Err(failure.into_error()),
+        }
+    }
+
+    /// The error of a query that didn't get an answer.
+    fn into_error(self) -> ResolveError {
+        match self {
+            Recorded::Timeout => ResolveErrorKind::Timeout.into(),
+            Recorded::Io(message) => std::io::Error::other(message).into(),
+            Recorded::Proto(message) => ProtoError::from(message).into(),
+            Recorded::Other(message) => ResolveErrorKind::Msg(message).into(),
+            Recorded::Records(_) | Recorded::NoRecords { .. } | Recorded::Message(_) => {
+                ResolveErrorKind::Message("the recorded query got an answer").into()
+            }
+        }
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>, ProtoError> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| ProtoError::from("invalid hex in recorded response"))
+}
+
+fn decode_record(hex: &str) -> Result<Record, ProtoError> {
+    Record::from_bytes(&decode_hex(hex)?)
+}
+
+/// The file the response to a query is stored in. The A and AAAA lookups of
+/// `lookup_ip` are stored together, with `ip` as the record type.
+fn path(dir: &Path, name: &str, record_type: Option<RecordType>) -> PathBuf {
+    let name = name.strip_suffix('.').unwrap_or(name).to_ascii_lowercase();
+    let record_type = match record_type {
+        Some(record_type) => record_type.to_string(),
+        None => "ip".to_string(),
+    };
+    dir.join(format!("{}.{}.json", name, record_type))
+}
+
+/// The file the answer of the name server at `ip` to a direct query is stored in.
+fn direct_path(dir: &Path, ip: IpAddr, name: &str, record_type: RecordType) -> PathBuf {
+    let name = name.strip_suffix('.').unwrap_or(name).to_ascii_lowercase();
+    dir.join(format!("{}.{}@{}.json", name, record_type, ip))
+}
+
+fn query(name: &str, record_type: RecordType) -> Result<Query, ResolveError> {
+    Ok(Query::query(Name::from_ascii(name)?, record_type))
+}
+
+/// Wraps a resolver and saves every response it gets in a directory, so the
+/// same scan can be run again later with `Replayer`.
+pub struct Recorder<R> {
+    resolver: R,
+    dir: PathBuf,
+}
+
+impl<R> Recorder<R> {
+    /// Records the responses of `resolver` to `dir`, which is created if it doesn't exist.
+    pub fn new(resolver: R, dir: &Path) -> std::io::Result<Recorder<R>> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Recorder {
+            resolver,
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    fn save(
+        &self,
+        name: &str,
+        record_type: Option<RecordType>,
+        result: Result<&Lookup, &ResolveError>,
+    ) {
+        self.write(&path(&self.dir, name, record_type), Recorded::new(result));
+    }
+
+    fn save_message(
+        &self,
+        ip: IpAddr,
+        name: &str,
+        record_type: RecordType,
+        result: Result<&Message, &ResolveError>,
+    ) {
+        let path = direct_path(&self.dir, ip, name, record_type);
+        self.write(&path, Recorded::of_message(result));
+    }
+
+    fn write(&self, path: &Path, recorded: Recorded) {
+        let saved = serde_json::to_vec(&recorded)
+            .map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(path, json));
+        if let Err(err) = saved {
+            warn!(path = %path.display(), %err, "failed to record the response");
+        }
+    }
+}
+
+impl<R: DnsLookup> DnsLookup for Recorder<R> {
+    fn lookup_ip(&self, name: &str) -> Result<LookupIp, ResolveError> {
+        let result = self.resolver.lookup_ip(name);
+        self.save(name, None, result.as_ref().map(LookupIp::as_lookup));
+        result
+    }
+
+    fn lookup(&self, name: &str, record_type: RecordType) -> Result<Lookup, ResolveError> {
+        let result = self.resolver.lookup(name, record_type);
+        self.save(name, Some(record_type), result.as_ref());
+        result
+    }
+
+    fn query_name_server(
+        &self,
+        ip: IpAddr,
+        name: &str,
+        record_type: RecordType,
+        options: &DirectOptions,
+    ) -> Result<Message, ResolveError> {
+        let result = self
+            .resolver
+            .query_name_server(ip, name, record_type, options);
+        self.save_message(ip, name, record_type, result.as_ref());
+        result
+    }
+}
+
+impl<R: AsyncDnsLookup> AsyncDnsLookup for Recorder<R> {
+    async fn lookup_ip(&self, name: &str) -> Result<LookupIp, ResolveError> {
+        let result = self.resolver.lookup_ip(name).await;
+        self.save(name, None, result.as_ref().map(LookupIp::as_lookup));
+        result
+    }
+
+    async fn lookup(&self, name: &str, record_type: RecordType) -> Result<Lookup, ResolveError> {
+        let result = self.resolver.lookup(name, record_type).await;
+        self.save(name, Some(record_type), result.as_ref());
+        result
+    }
+
+    async fn query_name_server(
+        &self,
+        ip: IpAddr,
+        name: &str,
+        record_type: RecordType,
+        options: &DirectOptions,
+    ) -> Result<Message, ResolveError> {
+        let result = self
+            .resolver
+            .query_name_server(ip, name, record_type, options)
+            .await;
+        self.save_message(ip, name, record_type, result.as_ref());
+        result
+    }
+}
+
+/// Answers the queries with the responses saved by `Recorder`, without
+/// sending anything over the network. Queries that weren't recorded fail
+/// with an io error.
+pub struct Replayer {
+    dir: PathBuf,
+}
+
+impl Replayer {
+    pub fn new(dir: &Path) -> Replayer {
+        Replayer {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    fn load(&self, name: &str, record_type: Option<RecordType>) -> Result<Lookup, ResolveError> {
+        let recorded = read(&path(&self.dir, name, record_type))?;
+        recorded.into_result(query(name, record_type.unwrap_or(RecordType::A))?)
+    }
+
+    fn load_message(
+        &self,
+        ip: IpAddr,
+        name: &str,
+        record_type: RecordType,
+    ) -> Result<Message, ResolveError> {
+        read(&direct_path(&self.dir, ip, name, record_type))?.into_message()
+    }
+}
+
+fn read(path: &Path) -> Result<Recorded, ResolveError> {
+    let json = std::fs::read(path).map_err(|err| match err.kind() {
+        ErrorKind::NotFound => std::io::Error::new(
+            ErrorKind::NotFound,
+            format!("no recorded response in {}", path.display()),
+        ),
+        _ => err,
+    })?;
+    Ok(serde_json::from_slice(&json).map_err(std::io::Error::from)?)
+}
+
+impl DnsLookup for Replayer {
+    fn lookup_ip(&self, name: &str) -> Result<LookupIp, ResolveError> {
+        self.load(name, None).map(LookupIp::from)
+    }
+
+    fn lookup(&self, name: &str, record_type: RecordType) -> Result<Lookup, ResolveError> {
+        self.load(name, Some(record_type))
+    }
+
+    fn query_name_server(
+        &self,
+        ip: IpAddr,
+        name: &str,
+        record_type: RecordType,
+        _: &DirectOptions,
+    ) -> Result<Message, ResolveError> {
+        self.load_message(ip, name, record_type)
+    }
+}
+
+impl AsyncDnsLookup for Replayer {
+    async fn lookup_ip(&self, name: &str) -> Result<LookupIp, ResolveError> {
+        self.load(name, None).map(LookupIp::from)
+    }
+
+    async fn lookup(&self, name: &str, record_type: RecordType) -> Result<Lookup, ResolveError> {
+        self.load(name, Some(record_type))
+    }
+
+    async fn query_name_server(
+        &self,
+        ip: IpAddr,
+        name: &str,
+        record_type: RecordType,
+        _: &DirectOptions,
+    ) -> Result<Message, ResolveError> {
+        self.load_message(ip, name, record_type)
+    }
+}
@@ -0,0 +1,94 @@
+use crate::direct::DirectOptions;
+use crate::lookup::AsyncDnsLookup;
+use futures::future::{LocalBoxFuture, Shared};
+use futures::FutureExt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use trust_dns_resolver::error::ResolveError;
+use trust_dns_resolver::lookup::Lookup;
+use trust_dns_resolver::lookup_ip::LookupIp;
+use trust_dns_resolver::proto::op::Message;
+use trust_dns_resolver::proto::rr::RecordType;
+
+type InFlight<'a, K, T> = RefCell<HashMap<K, Shared<LocalBoxFuture<'a, T>>>>;
+
+/// Wraps a resolver so that concurrent lookups of the same name and record
+/// type are sent only once, and all of them get the answer of that query.
+/// Nothing is kept once the query has completed, so a later lookup of the
+/// same name is sent again.
+pub struct SingleFlight<'a, R> {
+    resolver: &'a R,
+    ips: InFlight<'a, String, Result<LookupIp, ResolveError>>,
+    records: InFlight<'a, (String, RecordType), Result<Lookup, ResolveError>>,
+}
+
+impl<'a, R> SingleFlight<'a, R> {
+    pub fn new(resolver: &'a R) -> SingleFlight<'a, R> {
+        SingleFlight {
+            resolver,
+            ips: RefCell::new(HashMap::new()),
+            records: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+/// Waits for the lookup of `key` that is already in flight, or starts it
+/// with `start` if there isn't one.
+async fn coalesce<'a, K: Eq + Hash + Clone, T: Clone>(
+    in_flight: &InFlight<'a, K, T>,
+    key: K,
+    start: impl FnOnce() -> LocalBoxFuture<'a, T>,
+) -> T {
+    let lookup = in_flight
+        .borrow_mut()
+        .entry(key.clone())
+        .or_insert_with(|| start().shared())
+        .clone();
+    let result = lookup.await;
+
+    // the entry may already have been replaced by a newer lookup, that has
+    // to stay until it has completed as well
+    let mut in_flight = in_flight.borrow_mut();
+    if in_flight
+        .get(&key)
+        .is_some_and(|current| current.peek().is_some())
+    {
+        in_flight.remove(&key);
+    }
+    result
+}
+
+impl<'a, R: AsyncDnsLookup> AsyncDnsLookup for SingleFlight<'a, R> {
+    async fn lookup_ip(&self, name: &str) -> Result<LookupIp, ResolveError> {
+        let resolver = self.resolver;
+        let name = name.to_ascii_lowercase();
+        coalesce(&self.ips, name.clone(), move || {
+            async move { resolver.lookup_ip(&name).await }.boxed_local()
+        })
+        .await
+    }
+
+    async fn lookup(&self, name: &str, record_type: RecordType) -> Result<Lookup, ResolveError> {
+        let resolver = self.resolver;
+        let name = name.to_ascii_lowercase();
+        coalesce(&self.records, (name.clone(), record_type), move || {
+            async move { resolver.lookup(&name, record_type).await }.boxed_local()
+        })
+        .await
+    }
+
+    /// Not coalesced, each of these goes to another name server anyway.
+    async fn query_name_server(
+        &self,
+        ip: IpAddr,
+        name: &str,
+        record_type: RecordType,
+        options: &DirectOptions,
+    ) -> Result<Message, ResolveError> {
+        self.resolver
+            .query_name_server(ip, name, record_type, options)
+            .await
+    }
+}
@@ -0,0 +1,70 @@
+use nsdetect::{check_domain, LookupResult};
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use std::thread;
+use std::time::Duration;
+use trust_dns_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use trust_dns_resolver::proto::op::{Message, MessageType, ResponseCode};
+use trust_dns_resolver::Resolver;
+
+/// Starts a name server on localhost that drops the first `dropped` queries,
+/// like a lossy network would, and answers everything after that with SERVFAIL.
+fn flaky_servfail_server(dropped: usize) -> u16 {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let port = socket.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        let mut seen = 0;
+        while let Ok((len, src)) = socket.recv_from(&mut buf) {
+            seen += 1;
+            if seen <= dropped {
+                continue;
+            }
+
+            let query = Message::from_vec(&buf[..len]).unwrap();
+            let mut response =
+                Message::error_msg(query.id(), query.op_code(), ResponseCode::ServFail);
+            response.set_message_type(MessageType::Response);
+            response.add_queries(query.queries().to_vec());
+            socket.send_to(&response.to_vec().unwrap(), src).unwrap();
+        }
+    });
+
+    port
+}
+
+fn resolver(port: u16, attempts: usize) -> Resolver {
+    let mut opts = ResolverOpts::default();
+    opts.timeout = Duration::from_millis(200);
+    opts.attempts = attempts;
+
+    Resolver::new(
+        ResolverConfig::from_parts(
+            None,
+            vec![],
+            NameServerConfigGroup::from_ips_clear(&[IpAddr::V4(Ipv4Addr::LOCALHOST)], port, true),
+        ),
+        opts,
+    )
+    .unwrap()
+}
+
+#[test]
+fn servfail_is_missed_without_retries() {
+    let resolver = resolver(flaky_servfail_server(2), 0);
+
+    assert_ne!(
+        check_domain("flaky.example.", &resolver).result,
+        LookupResult::MaybeVulnerable
+    );
+}
+
+#[test]
+fn retries_recover_servfail_from_packet_loss() {
+    let resolver = resolver(flaky_servfail_server(2), 2);
+
+    assert_eq!(
+        check_domain("flaky.example.", &resolver).result,
+        LookupResult::MaybeVulnerable
+    );
+}
@@ -0,0 +1,25 @@
+mod common;
+
+use common::SlowLookup;
+use futures::StreamExt;
+use nsdetect::{check_stream, InspectOptions, LookupResult};
+use std::num::NonZeroUsize;
+
+#[test]
+fn stream_checks_every_domain_with_bounded_concurrency() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let resolver = SlowLookup::default();
+    let options = InspectOptions::default();
+    let domains = futures::stream::iter((0..10).map(|i| format!("www{}.example", i)));
+
+    let mut results: Vec<(String, LookupResult)> = runtime.block_on(
+        check_stream(domains, &resolver, &options, NonZeroUsize::new(3).unwrap())
+            .map(|(domain, outcome)| (domain, outcome.result))
+            .collect(),
+    );
+    results.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    assert_eq!(results.len(), 10);
+    assert_eq!(results[0], ("www0.example".to_string(), LookupResult::Safe));
+    assert_eq!(resolver.max_in_flight.get(), 3);
+}
@@ -0,0 +1,224 @@
+//! Fixtures shared by the integration tests and the benchmarks.
+#![allow(dead_code)]
+
+use nsdetect::direct::DirectOptions;
+use nsdetect::lookup::{AsyncDnsLookup, DnsLookup};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::lookup::Lookup;
+use trust_dns_resolver::lookup_ip::LookupIp;
+use trust_dns_resolver::proto::op::{Message, MessageType, Query, ResponseCode};
+use trust_dns_resolver::proto::rr::{Name, RData, Record, RecordType};
+
+pub fn query(name: &str, record_type: RecordType) -> Query {
+    Query::query(Name::from_ascii(name).unwrap(), record_type)
+}
+
+/// The error of an answer without records, with the given response code.
+pub fn no_records(
+    name: &str,
+    record_type: RecordType,
+    response_code: ResponseCode,
+) -> ResolveError {
+    ResolveErrorKind::NoRecordsFound {
+        query: Box::new(query(name, record_type)),
+        soa: None,
+        negative_ttl: None,
+        response_code,
+        trusted: true,
+    }
+    .into()
+}
+
+/// Answers from a fixed table of records, and with NXDOMAIN for everything else.
+#[derive(Default)]
+pub struct MockLookup {
+    pub addresses: HashMap<String, Ipv4Addr>,
+    pub cnames: HashMap<String, String>,
+    pub name_servers: HashMap<String, Vec<String>>,
+    pub errors: HashMap<String, ResponseCode>,
+    /// Response codes for the AAAA queries only
+    pub aaaa_errors: HashMap<String, ResponseCode>,
+    /// How many more SERVFAIL answers to give before answering normally
+    pub servfails_left: RefCell<HashMap<String, usize>>,
+    /// How many more lookups to fail with an io error before answering normally
+    pub io_errors_left: RefCell<HashMap<String, usize>>,
+    /// The response code the name server at each address answers the queries
+    /// sent to it directly with, and if it's authoritative. The others time out
+    pub direct_answers: HashMap<IpAddr, (ResponseCode, bool)>,
+}
+
+impl DnsLookup for MockLookup {
+    fn lookup_ip(&self, name: &str) -> Result<LookupIp, ResolveError> {
+        if let Some(left @ 1..) = self.io_errors_left.borrow_mut().get_mut(name) {
+            *left -= 1;
+            return Err(std::io::Error::from(std::io::ErrorKind::ConnectionReset).into());
+        }
+        if let Some(left @ 1..) = self.servfails_left.borrow_mut().get_mut(name) {
+            *left -= 1;
+            return Err(no_records(name, RecordType::A, ResponseCode::ServFail));
+        }
+        if let Some(code) = self.errors.get(name) {
+            return Err(no_records(name, RecordType::A, *code));
+        }
+        match self.addresses.get(name) {
+            Some(ip) => {
+                let query = query(name, RecordType::A);
+                Ok(Lookup::from_rdata(query, RData::A(*ip)).into())
+            }
+            None => Err(no_records(name, RecordType::A, ResponseCode::NXDomain)),
+        }
+    }
+
+    fn lookup(&self, name: &str, record_type: RecordType) -> Result<Lookup, ResolveError> {
+        if let Some(code) = self.errors.get(name) {
+            return Err(no_records(name, record_type, *code));
+        }
+        if let (RecordType::AAAA, Some(code)) = (record_type, self.aaaa_errors.get(name)) {
+            return Err(no_records(name, record_type, *code));
+        }
+        match self.addresses.get(name) {
+            Some(ip) if record_type == RecordType::A => {
+                let query = query(name, record_type);
+                return Ok(Lookup::from_rdata(query, RData::A(*ip)));
+            }
+            _ => {}
+        }
+        match self.cnames.get(name) {
+            Some(target) if record_type == RecordType::CNAME => {
+                let query = query(name, record_type);
+                let target = Name::from_ascii(target).unwrap();
+                return Ok(Lookup::from_rdata(query, RData::CNAME(target)));
+            }
+            _ => {}
+        }
+        match self.name_servers.get(name) {
+            Some(names) if record_type == RecordType::NS => {
+                let query = query(name, record_type);
+                let records: Vec<Record> = names
+                    .iter()
+                    .map(|ns| {
+                        let ns = Name::from_ascii(ns).unwrap();
+                        Record::from_rdata(query.name().clone(), 60, RData::NS(ns))
+                    })
+                    .collect();
+                Ok(Lookup::new_with_max_ttl(query, records.into()))
+            }
+            _ => Err(no_records(name, record_type, ResponseCode::NoError)),
+        }
+    }
+
+    fn query_name_server(
+        &self,
+        ip: IpAddr,
+        name: &str,
+        record_type: RecordType,
+        _: &DirectOptions,
+    ) -> Result<Message, ResolveError> {
+        let Some(&(response_code, authoritative)) = self.direct_answers.get(&ip) else {
+            return Err(ResolveErrorKind::Timeout.into());
+        };
+        let mut answer = Message::new();
+        answer
+            .set_message_type(MessageType::Response)
+            .set_response_code(response_code)
+            .set_authoritative(authoritative)
+            .add_query(query(name, record_type));
+        Ok(answer)
+    }
+}
+
+impl AsyncDnsLookup for MockLookup {
+    async fn lookup_ip(&self, name: &str) -> Result<LookupIp, ResolveError> {
+        DnsLookup::lookup_ip(self, name)
+    }
+
+    async fn lookup(&self, name: &str, record_type: RecordType) -> Result<Lookup, ResolveError> {
+        DnsLookup::lookup(self, name, record_type)
+    }
+
+    async fn query_name_server(
+        &self,
+        ip: IpAddr,
+        name: &str,
+        record_type: RecordType,
+        options: &DirectOptions,
+    ) -> Result<Message, ResolveError> {
+        DnsLookup::query_name_server(self, ip, name, record_type, options)
+    }
+}
+
+/// Answers the address queries with the same address after a short while,
+/// and everything else without records. Counts the queries, and keeps track
+/// of how many of them were in flight at most.
+#[derive(Default)]
+pub struct SlowLookup {
+    pub queries: Cell<usize>,
+    in_flight: Cell<usize>,
+    pub max_in_flight: Cell<usize>,
+}
+
+impl AsyncDnsLookup for SlowLookup {
+    async fn lookup_ip(&self, name: &str) -> Result<LookupIp, ResolveError> {
+        self.lookup(name, RecordType::A).await.map(LookupIp::from)
+    }
+
+    async fn lookup(&self, name: &str, record_type: RecordType) -> Result<Lookup, ResolveError> {
+        self.queries.set(self.queries.get() + 1);
+        self.in_flight.set(self.in_flight.get() + 1);
+        self.max_in_flight
+            .set(self.max_in_flight.get().max(self.in_flight.get()));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        self.in_flight.set(self.in_flight.get() - 1);
+
+        match record_type {
+            RecordType::A => Ok(Lookup::from_rdata(
+                query(name, record_type),
+                RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+            )),
+            _ => Err(no_records(name, record_type, ResponseCode::NoError)),
+        }
+    }
+
+    async fn query_name_server(
+        &self,
+        _: IpAddr,
+        _: &str,
+        _: RecordType,
+        _: &DirectOptions,
+    ) -> Result<Message, ResolveError> {
+        Err(ResolveErrorKind::Timeout.into())
+    }
+}
+
+/// A directory of its own for a test, that is removed again when it's dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "nsdetect-{}-{}-{}",
+            name,
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
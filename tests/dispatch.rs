@@ -0,0 +1,87 @@
+mod common;
+
+use common::SlowLookup;
+use nsdetect::dispatch::{dispatch, DispatchOptions};
+use nsdetect::{inspect_domain_async, CheckOutcome, InspectOptions, LookupResult};
+use std::convert::Infallible;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+/// Dispatches `domains` to the `SlowLookup`, with the outcome from the cache
+/// for the ones that have one, and returns the results in input order.
+fn dispatch_all(
+    resolver: &SlowLookup,
+    domains: Vec<(String, Option<CheckOutcome>)>,
+    options: &DispatchOptions,
+) -> Vec<(usize, String, LookupResult)> {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let inspect_options = &InspectOptions::default();
+    let mut results = vec![];
+    runtime
+        .block_on(dispatch(
+            domains.into_iter().map(Ok),
+            options,
+            &AtomicBool::new(false),
+            |domain| async move { inspect_domain_async(&domain, resolver, inspect_options).await },
+            |i, domain, outcome| {
+                results.push((i, domain, outcome.result));
+                Ok::<_, Infallible>(())
+            },
+        ))
+        .unwrap();
+    results.sort_by_key(|(i, _, _)| *i);
+    results
+}
+
+#[test]
+fn dispatch_checks_the_domains_that_arent_cached() {
+    let resolver = SlowLookup::default();
+    let mut cached = CheckOutcome::timed_out();
+    cached.result = LookupResult::MaybeVulnerable;
+    let domains = (0..10)
+        .map(|i| match i {
+            4 => (format!("www{}.example", i), Some(cached.clone())),
+            _ => (format!("www{}.example", i), None),
+        })
+        .collect();
+    let options = DispatchOptions {
+        concurrency: 3,
+        ..DispatchOptions::default()
+    };
+
+    let results = dispatch_all(&resolver, domains, &options);
+
+    assert_eq!(results.len(), 10);
+    assert!(results.iter().enumerate().all(|(n, (i, _, _))| n == *i));
+    assert_eq!(
+        results[4],
+        (4, "www4.example".to_string(), LookupResult::MaybeVulnerable)
+    );
+    assert!(results
+        .iter()
+        .filter(|(i, _, _)| *i != 4)
+        .all(|(_, _, result)| *result == LookupResult::Safe));
+    assert_eq!(resolver.max_in_flight.get(), 3);
+}
+
+#[test]
+fn dispatch_times_out_the_domains_not_checked_in_time() {
+    let resolver = SlowLookup::default();
+    let domains = (0..5)
+        .map(|i| (format!("www{}.example", i), None))
+        .collect();
+    // every lookup takes longer than the whole dispatch may
+    let options = DispatchOptions {
+        concurrency: 2,
+        max_runtime: Some(Duration::from_millis(5)),
+        ..DispatchOptions::default()
+    };
+
+    let results = dispatch_all(&resolver, domains, &options);
+
+    assert_eq!(results.len(), 5);
+    assert!(results.iter().enumerate().all(|(n, (i, _, _))| n == *i));
+    assert!(results
+        .iter()
+        .all(|(_, _, result)| *result == LookupResult::TimedOut));
+}
@@ -0,0 +1,249 @@
+mod common;
+
+use common::TempDir;
+use std::io::Write;
+use std::net::UdpSocket;
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+/// Runs nsdetect on `input` from stdin, and returns what it printed to stdout and stderr.
+fn scan(input: &str) -> (String, String) {
+    scan_with(&[], input)
+}
+
+/// Like `scan`, with extra arguments for nsdetect.
+fn scan_with(args: &[&str], input: &str) -> (String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nsdetect"))
+        .arg("--no-color")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn empty_lines_are_not_looked_up() {
+    let (stdout, stderr) = scan("\n   \r\n.\n# comment\n\n");
+
+    assert_eq!(stdout, "");
+    assert!(stderr.contains("Scanned 0:"), "{}", stderr);
+}
+
+#[test]
+fn jobs_keep_the_input_order() {
+    // nothing has been recorded, so every lookup fails right away and the
+    // threads race each other
+    let dir = TempDir::new("jobs");
+    let domains: Vec<String> = (0..200).map(|i| format!("d{}.example", i)).collect();
+    let replay = dir.path().to_str().unwrap();
+    let (stdout, stderr) = scan_with(
+        &["--replay", replay, "--jobs", "4", "--format", "ndjson"],
+        &domains.join("\n"),
+    );
+
+    let printed: Vec<String> = stdout
+        .lines()
+        .map(|line| {
+            let result: serde_json::Value = serde_json::from_str(line).unwrap();
+            result["domain"].as_str().unwrap().to_string()
+        })
+        .collect();
+    assert_eq!(printed, domains, "{}", stderr);
+}
+
+/// Scans three domains with a name server that never answers, so the first
+/// lookup outlasts the --max-runtime of the scan, and returns the domains and
+/// results it printed.
+fn scan_out_of_time(args: &[&str]) -> Vec<(String, String)> {
+    let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let port = silent.local_addr().unwrap().port().to_string();
+    let mut all_args = vec![
+        "--name-server",
+        "127.0.0.1",
+        "--port",
+        &port,
+        "--timeout",
+        "0.5",
+        "--attempts",
+        "1",
+        "--max-runtime",
+        "1s",
+        "--format",
+        "ndjson",
+    ];
+    all_args.extend_from_slice(args);
+    let (stdout, stderr) = scan_with(&all_args, "a.example\nb.example\nc.example\n");
+
+    let printed: Vec<(String, String)> = stdout
+        .lines()
+        .map(|line| {
+            let result: serde_json::Value = serde_json::from_str(line).unwrap();
+            (
+                result["domain"].as_str().unwrap().to_string(),
+                result["result"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect();
+    let domains: Vec<_> = printed.iter().map(|(domain, _)| domain.as_str()).collect();
+    assert_eq!(
+        domains,
+        ["a.example", "b.example", "c.example"],
+        "{}",
+        stderr
+    );
+    printed
+}
+
+#[test]
+fn domains_not_started_in_time_are_timed_out() {
+    let printed = scan_out_of_time(&[]);
+
+    assert_eq!(printed[1].1, "TimedOut");
+    assert_eq!(printed[2].1, "TimedOut");
+}
+
+#[test]
+fn domains_not_dispatched_in_time_are_timed_out_in_async_mode() {
+    // only the first domain is in flight when the time runs out
+    let printed = scan_out_of_time(&["--async", "--concurrency", "1", "--ordered"]);
+
+    for (_, result) in &printed {
+        assert_eq!(result, "TimedOut");
+    }
+}
+
+#[test]
+fn watch_looks_up_cached_domains_again_after_the_first_round() {
+    let dir = TempDir::new("watch-cache");
+    let cache = dir.path().join("cache.json");
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    std::fs::write(
+        &cache,
+        format!(
+            r#"{{"www.example": {{"checked_at": {}, "result": "MaybeVulnerable"}}}}"#,
+            now
+        ),
+    )
+    .unwrap();
+
+    // nothing has been recorded, so looking the domain up fails
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nsdetect"))
+        .arg("--no-color")
+        .arg("--replay")
+        .arg(dir.path())
+        .arg("--cache")
+        .arg(&cache)
+        .args(["--watch", "1s", "--format", "ndjson", "-d", "www.example"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(1500));
+    child.kill().unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    let results: Vec<String> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let result: serde_json::Value = serde_json::from_str(line).unwrap();
+            result["result"].as_str().unwrap().to_string()
+        })
+        .collect();
+    assert_eq!(results, ["MaybeVulnerable", "LookupError"]);
+}
+
+#[test]
+fn html_report_shows_when_each_domain_was_checked() {
+    let dir = TempDir::new("html-checked-at");
+    let cache = dir.path().join("cache.json");
+    let an_hour_ago = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        - 3600;
+    std::fs::write(
+        &cache,
+        format!(
+            r#"{{"www.example": {{"checked_at": {}, "result": "Safe"}}}}"#,
+            an_hour_ago
+        ),
+    )
+    .unwrap();
+
+    let before = SystemTime::now() - Duration::from_secs(1);
+    let output = Command::new(env!("CARGO_BIN_EXE_nsdetect"))
+        .arg("--no-color")
+        .arg("--replay")
+        .arg(dir.path())
+        .arg("--cache")
+        .arg(&cache)
+        .args(["--format", "html", "-d", "www.example", "-d", "new.example"])
+        .output()
+        .unwrap();
+    let html = String::from_utf8(output.stdout).unwrap();
+    assert!(html.contains("<th>Checked at</th>"));
+
+    let checked_at = |domain: &str| {
+        let row = html
+            .lines()
+            .find(|line| line.contains(&format!("<td>{}</td>", domain)))
+            .unwrap();
+        let cell = row
+            .rsplit("<td>")
+            .next()
+            .unwrap()
+            .trim_end_matches("</td></tr>");
+        humantime::parse_rfc3339(cell).unwrap()
+    };
+    // the cached result keeps the time it was looked up
+    assert_eq!(
+        checked_at("www.example"),
+        SystemTime::UNIX_EPOCH + Duration::from_secs(an_hour_ago)
+    );
+    assert!(checked_at("new.example") >= before);
+}
+
+#[test]
+fn confirm_is_rejected_with_replay() {
+    let dir = TempDir::new("confirm-replay");
+    let replay = dir.path().to_str().unwrap();
+    let (stdout, stderr) = scan_with(&["--replay", replay, "--confirm"], "www.example\n");
+
+    assert_eq!(stdout, "");
+    assert!(stderr.contains("cannot be used with"), "{}", stderr);
+}
+
+#[test]
+fn errors_are_reported_on_one_line() {
+    let dir = TempDir::new("missing-input");
+    let missing = dir.path().join("domains.txt");
+    let (stdout, stderr) = scan_with(&["--input-file", missing.to_str().unwrap()], "");
+
+    assert_eq!(stdout, "");
+    assert_eq!(stderr.lines().count(), 1, "{}", stderr);
+    assert!(
+        stderr.starts_with(&format!(
+            "error: failed to read input file {}: ",
+            missing.display()
+        )),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use nsdetect::{is_vulnerable, is_vulnerable_with, ErrorKind, LookupResult};
+use std::net::Ipv4Addr;
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::lookup::Lookup;
+use trust_dns_resolver::lookup_ip::LookupIp;
+use trust_dns_resolver::proto::error::ProtoError;
+use trust_dns_resolver::proto::op::{Query, ResponseCode};
+use trust_dns_resolver::proto::rr::{RData, RecordType};
+
+fn query() -> Query {
+    common::query("example.com.", RecordType::A)
+}
+
+fn no_records(response_code: ResponseCode) -> Result<LookupIp, ResolveError> {
+    Err(common::no_records(
+        "example.com.",
+        RecordType::A,
+        response_code,
+    ))
+}
+
+#[test]
+fn answer_is_safe() {
+    let lookup = Lookup::from_rdata(query(), RData::A(Ipv4Addr::new(192, 0, 2, 1)));
+
+    assert_eq!(is_vulnerable(&Ok(lookup.into())), LookupResult::Safe);
+}
+
+#[test]
+fn servfail_is_maybe_vulnerable() {
+    assert_eq!(
+        is_vulnerable(&no_records(ResponseCode::ServFail)),
+        LookupResult::MaybeVulnerable
+    );
+}
+
+#[test]
+fn every_other_response_code_is_safe() {
+    // response codes are 12 bits, with the extended ones from EDNS
+    for code in (0..4096u16).map(<ResponseCode as From<u16>>::from) {
+        if code == ResponseCode::ServFail {
+            continue;
+        }
+        assert_eq!(
+            is_vulnerable(&no_records(code)),
+            LookupResult::Safe,
+            "{:?}",
+            code
+        );
+    }
+}
+
+#[test]
+fn protocol_error_is_lookup_error() {
+    let err = ResolveErrorKind::Proto(ProtoError::from("malformed message")).into();
+
+    assert_eq!(is_vulnerable(&Err(err)), LookupResult::LookupError);
+}
+
+#[test]
+fn io_error_is_lookup_error() {
+    let err = ResolveErrorKind::Io(std::io::Error::from(std::io::ErrorKind::ConnectionRefused));
+
+    assert_eq!(is_vulnerable(&Err(err.into())), LookupResult::LookupError);
+}
+
+#[test]
+fn timeout_is_safe() {
+    let err = ResolveErrorKind::Timeout.into();
+
+    assert_eq!(is_vulnerable(&Err(err)), LookupResult::Safe);
+}
+
+#[test]
+fn extra_codes_are_maybe_vulnerable() {
+    let refused = no_records(ResponseCode::Refused);
+
+    assert_eq!(
+        is_vulnerable_with(&refused, &[ResponseCode::Refused]),
+        LookupResult::MaybeVulnerable
+    );
+    assert_eq!(
+        is_vulnerable_with(
+            &no_records(ResponseCode::ServFail),
+            &[ResponseCode::Refused]
+        ),
+        LookupResult::MaybeVulnerable
+    );
+    assert_eq!(
+        is_vulnerable_with(
+            &no_records(ResponseCode::NXDomain),
+            &[ResponseCode::Refused]
+        ),
+        LookupResult::Safe
+    );
+}
+
+#[test]
+fn error_kind_tells_the_failures_apart() {
+    let timeout: Result<LookupIp, ResolveError> = Err(ResolveErrorKind::Timeout.into());
+    let io: Result<LookupIp, ResolveError> = Err(ResolveErrorKind::Io(std::io::Error::from(
+        std::io::ErrorKind::ConnectionRefused,
+    ))
+    .into());
+    let refused: Result<LookupIp, ResolveError> =
+        Err(ProtoError::from(std::io::Error::from(std::io::ErrorKind::ConnectionRefused)).into());
+
+    assert_eq!(ErrorKind::of(&timeout), Some(ErrorKind::Timeout));
+    assert_eq!(ErrorKind::of(&io), Some(ErrorKind::Io));
+    assert_eq!(ErrorKind::of(&refused), Some(ErrorKind::Io));
+    assert_eq!(ErrorKind::of(&no_records(ResponseCode::NXDomain)), None);
+}
@@ -0,0 +1,443 @@
+mod common;
+
+use common::MockLookup;
+use nsdetect::direct::DirectOptions;
+use nsdetect::{
+    check_domain, compare_answers, inspect_domain, inspect_domain_async, ErrorKind, InspectOptions,
+    LookupResult, Severity,
+};
+use std::net::{IpAddr, Ipv4Addr};
+use trust_dns_resolver::proto::op::ResponseCode;
+use trust_dns_resolver::proto::rr::RecordType;
+
+#[test]
+fn servfail_is_maybe_vulnerable() {
+    let mut resolver = MockLookup::default();
+    resolver
+        .errors
+        .insert("broken.example".to_string(), ResponseCode::ServFail);
+
+    let outcome = inspect_domain("broken.example", &resolver, &InspectOptions::default());
+
+    assert_eq!(outcome.result, LookupResult::MaybeVulnerable);
+    assert_eq!(outcome.response_code, Some(ResponseCode::ServFail));
+    assert_eq!(
+        check_domain("broken.example", &resolver).result,
+        LookupResult::MaybeVulnerable
+    );
+}
+
+#[test]
+fn resolved_domain_is_safe_with_its_addresses() {
+    let mut resolver = MockLookup::default();
+    resolver
+        .addresses
+        .insert("www.example".to_string(), Ipv4Addr::new(192, 0, 2, 1));
+
+    let outcome = inspect_domain("www.example", &resolver, &InspectOptions::default());
+
+    assert_eq!(outcome.result, LookupResult::Safe);
+    assert_eq!(outcome.ips, vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]);
+
+    let outcome = check_domain("www.example", &resolver);
+    assert_eq!(outcome.result, LookupResult::Safe);
+    assert_eq!(outcome.ips, vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]);
+    assert!(outcome.duration_ms.is_some());
+}
+
+#[test]
+fn dangling_cname_to_a_provider_is_maybe_vulnerable() {
+    let mut resolver = MockLookup::default();
+    resolver
+        .cnames
+        .insert("docs.example".to_string(), "gone.github.io.".to_string());
+
+    let outcome = inspect_domain("docs.example", &resolver, &InspectOptions::default());
+
+    assert_eq!(outcome.result, LookupResult::MaybeVulnerable);
+    assert_eq!(outcome.provider.as_deref(), Some("GitHub Pages"));
+}
+
+#[test]
+fn cname_to_a_name_that_does_not_exist_is_maybe_vulnerable() {
+    let mut resolver = MockLookup::default();
+    resolver
+        .cnames
+        .insert("docs.example".to_string(), "gone.example.net.".to_string());
+
+    let outcome = inspect_domain("docs.example", &resolver, &InspectOptions::default());
+
+    assert_eq!(outcome.result, LookupResult::MaybeVulnerable);
+    assert_eq!(outcome.provider, None);
+    assert_eq!(outcome.dangling_cname.as_deref(), Some("gone.example.net"));
+}
+
+#[test]
+fn cname_to_an_unknown_target_without_records_is_safe() {
+    let mut resolver = MockLookup::default();
+    resolver
+        .cnames
+        .insert("docs.example".to_string(), "empty.example.net.".to_string());
+    resolver
+        .errors
+        .insert("empty.example.net.".to_string(), ResponseCode::NoError);
+
+    let outcome = inspect_domain("docs.example", &resolver, &InspectOptions::default());
+
+    assert_eq!(outcome.result, LookupResult::Safe);
+    assert_eq!(outcome.provider, None);
+    assert_eq!(outcome.dangling_cname, None);
+}
+
+#[test]
+fn record_type_is_queried_instead_of_the_addresses() {
+    let mut resolver = MockLookup::default();
+    resolver
+        .addresses
+        .insert("www.example".to_string(), Ipv4Addr::new(192, 0, 2, 1));
+    resolver
+        .errors
+        .insert("mail.example".to_string(), ResponseCode::ServFail);
+    let options = InspectOptions {
+        record_type: Some(RecordType::MX),
+        ..InspectOptions::default()
+    };
+
+    let outcome = inspect_domain("www.example", &resolver, &options);
+    assert_eq!(outcome.result, LookupResult::Safe);
+    assert_eq!(outcome.response_code, Some(ResponseCode::NoError));
+    assert!(outcome.ips.is_empty());
+
+    let outcome = inspect_domain("mail.example", &resolver, &options);
+    assert_eq!(outcome.result, LookupResult::MaybeVulnerable);
+    assert_eq!(outcome.response_code, Some(ResponseCode::ServFail));
+}
+
+#[test]
+fn name_server_in_an_unregistered_domain_is_maybe_vulnerable() {
+    let mut resolver = MockLookup::default();
+    resolver
+        .addresses
+        .insert("www.example".to_string(), Ipv4Addr::new(192, 0, 2, 1));
+    resolver.name_servers.insert(
+        "www.example".to_string(),
+        vec![
+            "ns1.dns.example.".to_string(),
+            "ns2.gone.co.uk.".to_string(),
+        ],
+    );
+    resolver
+        .errors
+        .insert("gone.co.uk".to_string(), ResponseCode::NXDomain);
+    let options = InspectOptions {
+        unregistered_ns: true,
+        ..InspectOptions::default()
+    };
+
+    let outcome = inspect_domain("www.example", &resolver, &options);
+
+    assert_eq!(outcome.result, LookupResult::MaybeVulnerable);
+    assert_eq!(
+        outcome.unregistered_ns_domain.as_deref(),
+        Some("gone.co.uk")
+    );
+
+    resolver.errors.clear();
+    let outcome = inspect_domain("www.example", &resolver, &options);
+
+    assert_eq!(outcome.result, LookupResult::Safe);
+    assert_eq!(outcome.unregistered_ns_domain, None);
+}
+
+/// A domain delegated to ns1 and ns2, that answer the queries sent to them
+/// directly with the given response codes and authoritative flags.
+fn delegated(ns1: (ResponseCode, bool), ns2: (ResponseCode, bool)) -> MockLookup {
+    let mut resolver = MockLookup::default();
+    resolver
+        .addresses
+        .insert("www.example".to_string(), Ipv4Addr::new(192, 0, 2, 1));
+    resolver.name_servers.insert(
+        "www.example".to_string(),
+        vec![
+            "ns1.dns.example.".to_string(),
+            "ns2.dns.example.".to_string(),
+        ],
+    );
+    for (name, ip, answer) in [
+        ("ns1.dns.example.", Ipv4Addr::new(192, 0, 2, 53), ns1),
+        ("ns2.dns.example.", Ipv4Addr::new(192, 0, 2, 54), ns2),
+    ] {
+        resolver.addresses.insert(name.to_string(), ip);
+        resolver.direct_answers.insert(IpAddr::V4(ip), answer);
+    }
+    resolver
+}
+
+#[test]
+fn lame_name_server_is_maybe_vulnerable() {
+    let options = InspectOptions {
+        lame_delegation: Some(DirectOptions::default()),
+        ..InspectOptions::default()
+    };
+
+    let resolver = delegated(
+        (ResponseCode::NoError, true),
+        (ResponseCode::Refused, false),
+    );
+    let outcome = inspect_domain("www.example", &resolver, &options);
+    assert_eq!(outcome.result, LookupResult::MaybeVulnerable);
+    let lame = outcome.lame_name_server.unwrap();
+    assert_eq!(lame.name, "ns2.dns.example");
+    assert_eq!(lame.ip, IpAddr::V4(Ipv4Addr::new(192, 0, 2, 54)));
+    assert_eq!(lame.response_code, Some(ResponseCode::Refused));
+
+    // an answer without the authoritative flag is lame as well
+    let resolver = delegated(
+        (ResponseCode::NoError, false),
+        (ResponseCode::NoError, true),
+    );
+    let outcome =
+        futures::executor::block_on(inspect_domain_async("www.example", &resolver, &options));
+    assert_eq!(outcome.result, LookupResult::MaybeVulnerable);
+    let lame = outcome.lame_name_server.unwrap();
+    assert_eq!(lame.name, "ns1.dns.example");
+    assert_eq!(lame.response_code, None);
+}
+
+#[test]
+fn authoritative_name_servers_are_not_lame() {
+    let options = InspectOptions {
+        lame_delegation: Some(DirectOptions::default()),
+        ..InspectOptions::default()
+    };
+    let mut resolver = delegated((ResponseCode::NoError, true), (ResponseCode::NoError, true));
+
+    let outcome = inspect_domain("www.example", &resolver, &options);
+    assert_eq!(outcome.result, LookupResult::Safe);
+    assert_eq!(outcome.lame_name_server, None);
+
+    // name servers that don't answer at all aren't reported
+    resolver.direct_answers.clear();
+    let outcome =
+        futures::executor::block_on(inspect_domain_async("www.example", &resolver, &options));
+    assert_eq!(outcome.result, LookupResult::Safe);
+    assert_eq!(outcome.lame_name_server, None);
+}
+
+#[test]
+fn servfail_is_retried() {
+    let mut resolver = MockLookup::default();
+    resolver
+        .addresses
+        .insert("flaky.example".to_string(), Ipv4Addr::new(192, 0, 2, 1));
+    let mut options = InspectOptions {
+        servfail_retries: 1,
+        ..InspectOptions::default()
+    };
+
+    resolver
+        .servfails_left
+        .borrow_mut()
+        .insert("flaky.example".to_string(), 2);
+    let outcome = inspect_domain("flaky.example", &resolver, &options);
+    assert_eq!(outcome.result, LookupResult::MaybeVulnerable);
+
+    options.servfail_retries = 2;
+    resolver
+        .servfails_left
+        .borrow_mut()
+        .insert("flaky.example".to_string(), 2);
+    let outcome = inspect_domain("flaky.example", &resolver, &options);
+    assert_eq!(outcome.result, LookupResult::Safe);
+}
+
+#[test]
+fn io_error_is_retried() {
+    let mut resolver = MockLookup::default();
+    resolver
+        .addresses
+        .insert("flaky.example".to_string(), Ipv4Addr::new(192, 0, 2, 1));
+    let mut options = InspectOptions {
+        io_retries: 1,
+        ..InspectOptions::default()
+    };
+
+    resolver
+        .io_errors_left
+        .borrow_mut()
+        .insert("flaky.example".to_string(), 2);
+    let outcome = inspect_domain("flaky.example", &resolver, &options);
+    assert_eq!(outcome.result, LookupResult::LookupError);
+    assert_eq!(outcome.error_kind, Some(ErrorKind::Io));
+
+    options.io_retries = 2;
+    resolver
+        .io_errors_left
+        .borrow_mut()
+        .insert("flaky.example".to_string(), 2);
+    let outcome = inspect_domain("flaky.example", &resolver, &options);
+    assert_eq!(outcome.result, LookupResult::Safe);
+    // the 300ms of backoff before the retries isn't part of the lookup time
+    assert!(
+        outcome.duration_ms.unwrap() < 100,
+        "{:?}",
+        outcome.duration_ms
+    );
+}
+
+#[test]
+fn ip_versions_are_checked_separately() {
+    let mut resolver = MockLookup::default();
+    resolver
+        .addresses
+        .insert("www.example".to_string(), Ipv4Addr::new(192, 0, 2, 1));
+    let options = InspectOptions {
+        ip_versions: true,
+        ..InspectOptions::default()
+    };
+
+    let outcome = inspect_domain("www.example", &resolver, &options);
+    assert_eq!(outcome.result, LookupResult::Safe);
+    assert_eq!(outcome.ips, vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]);
+    let versions = outcome.ip_versions.unwrap();
+    assert!(versions.a.present);
+    assert!(!versions.aaaa.present);
+    assert_eq!(versions.aaaa.response_code, Some(ResponseCode::NoError));
+
+    resolver
+        .aaaa_errors
+        .insert("www.example".to_string(), ResponseCode::ServFail);
+    let outcome = inspect_domain("www.example", &resolver, &options);
+    assert_eq!(outcome.result, LookupResult::MaybeVulnerable);
+    let versions = outcome.ip_versions.unwrap();
+    assert_eq!(versions.a.result, LookupResult::Safe);
+    assert_eq!(versions.aaaa.result, LookupResult::MaybeVulnerable);
+
+    let outcome = inspect_domain("www.example", &resolver, &InspectOptions::default());
+    assert_eq!(outcome.ip_versions, None);
+}
+
+#[test]
+fn severity_depends_on_the_detection() {
+    let mut resolver = MockLookup::default();
+    resolver
+        .errors
+        .insert("broken.example".to_string(), ResponseCode::ServFail);
+    resolver
+        .cnames
+        .insert("docs.example".to_string(), "gone.github.io.".to_string());
+    resolver
+        .cnames
+        .insert("www.example".to_string(), "gone.example.net.".to_string());
+    resolver
+        .addresses
+        .insert("safe.example".to_string(), Ipv4Addr::new(192, 0, 2, 1));
+    let options = InspectOptions::default();
+
+    let severity = |domain| inspect_domain(domain, &resolver, &options).severity;
+    assert_eq!(severity("broken.example"), Some(Severity::Low));
+    assert_eq!(severity("www.example"), Some(Severity::Medium));
+    assert_eq!(severity("docs.example"), Some(Severity::High));
+    assert_eq!(severity("safe.example"), None);
+}
+
+#[test]
+fn cname_chain_is_kept_when_asked_for() {
+    let mut resolver = MockLookup::default();
+    resolver
+        .cnames
+        .insert("www.example".to_string(), "cdn.example.net.".to_string());
+    resolver.cnames.insert(
+        "cdn.example.net.".to_string(),
+        "gone.github.io.".to_string(),
+    );
+    let options = InspectOptions {
+        cname_chain: true,
+        ..InspectOptions::default()
+    };
+
+    let outcome = inspect_domain("www.example", &resolver, &options);
+    assert_eq!(
+        outcome.cname_chain,
+        vec!["cdn.example.net", "gone.github.io"]
+    );
+    assert_eq!(outcome.severity, Some(Severity::High));
+
+    let outcome = inspect_domain("www.example", &resolver, &InspectOptions::default());
+    assert!(outcome.cname_chain.is_empty());
+}
+
+#[test]
+fn cname_loop_is_a_lookup_error_with_the_loop() {
+    let mut resolver = MockLookup::default();
+    resolver
+        .cnames
+        .insert("a.example".to_string(), "b.example.".to_string());
+    resolver
+        .cnames
+        .insert("b.example.".to_string(), "a.example.".to_string());
+
+    let outcome = inspect_domain("a.example", &resolver, &InspectOptions::default());
+    assert_eq!(outcome.result, LookupResult::LookupError);
+    assert_eq!(outcome.error_kind, Some(ErrorKind::CnameLoop));
+    assert_eq!(outcome.cname_chain, vec!["b.example", "a.example"]);
+
+    resolver
+        .cnames
+        .insert("b.example.".to_string(), "c.example.".to_string());
+    resolver
+        .cnames
+        .insert("c.example.".to_string(), "gone.example.net.".to_string());
+    let options = InspectOptions {
+        max_cname_depth: 2,
+        ..InspectOptions::default()
+    };
+
+    let outcome = inspect_domain("a.example", &resolver, &options);
+    assert_eq!(outcome.result, LookupResult::LookupError);
+    assert_eq!(outcome.error_kind, Some(ErrorKind::CnameTooLong));
+    assert_eq!(outcome.cname_chain, vec!["b.example", "c.example"]);
+}
+
+#[test]
+fn name_servers_that_disagree_are_inconsistent() {
+    let mut answering = MockLookup::default();
+    answering
+        .addresses
+        .insert("www.example".to_string(), Ipv4Addr::new(192, 0, 2, 1));
+    let agreeing = answering.addresses.clone();
+    let nxdomain = MockLookup::default();
+    let options = InspectOptions::default();
+    let compared = |other: &MockLookup| {
+        compare_answers(vec![
+            (
+                "192.0.2.53".to_string(),
+                inspect_domain("www.example", &answering, &options),
+            ),
+            (
+                "198.51.100.53".to_string(),
+                inspect_domain("www.example", other, &options),
+            ),
+        ])
+    };
+
+    let outcome = compared(&nxdomain);
+    assert_eq!(outcome.result, LookupResult::Inconsistent);
+    assert_eq!(outcome.answers.len(), 2);
+    assert_eq!(
+        outcome.answers[0].ips,
+        vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]
+    );
+    assert_eq!(
+        outcome.answers[1].response_code,
+        Some(ResponseCode::NXDomain)
+    );
+
+    let outcome = compared(&MockLookup {
+        addresses: agreeing,
+        ..MockLookup::default()
+    });
+    assert_eq!(outcome.result, LookupResult::Safe);
+    assert_eq!(outcome.ips, vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]);
+    assert_eq!(outcome.answers.len(), 2);
+}
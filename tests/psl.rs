@@ -0,0 +1,23 @@
+use nsdetect::psl::registrable_domain;
+
+#[test]
+fn registrable_domain_follows_the_public_suffix_list() {
+    assert_eq!(
+        registrable_domain("ns1.example.com.").as_deref(),
+        Some("example.com")
+    );
+    assert_eq!(
+        registrable_domain("www.example.co.uk").as_deref(),
+        Some("example.co.uk")
+    );
+    assert_eq!(
+        registrable_domain("Pages.User.GitHub.io").as_deref(),
+        Some("user.github.io")
+    );
+}
+
+#[test]
+fn public_suffixes_are_not_registrable() {
+    assert_eq!(registrable_domain("co.uk"), None);
+    assert_eq!(registrable_domain("com"), None);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use common::{MockLookup, TempDir};
+use nsdetect::direct::DirectOptions;
+use nsdetect::lookup::DnsLookup;
+use nsdetect::replay::{Recorder, Replayer};
+use nsdetect::{inspect_domain, InspectOptions, LookupResult};
+use std::net::{IpAddr, Ipv4Addr};
+use trust_dns_resolver::proto::op::ResponseCode;
+use trust_dns_resolver::proto::rr::RecordType;
+
+#[test]
+fn replay_gives_the_recorded_outcomes() {
+    let dir = TempDir::new("replay");
+    let options = InspectOptions::default();
+    let mut resolver = MockLookup::default();
+    resolver
+        .addresses
+        .insert("www.example".to_string(), Ipv4Addr::new(192, 0, 2, 1));
+    resolver
+        .errors
+        .insert("broken.example".to_string(), ResponseCode::ServFail);
+
+    let recorder = Recorder::new(resolver, dir.path()).unwrap();
+    let recorded: Vec<_> = ["www.example", "broken.example"]
+        .iter()
+        .map(|domain| inspect_domain(domain, &recorder, &options))
+        .collect();
+
+    let replayer = Replayer::new(dir.path());
+    let www = inspect_domain("www.example", &replayer, &options);
+    let broken = inspect_domain("broken.example", &replayer, &options);
+    let unknown = inspect_domain("unknown.example", &replayer, &options);
+
+    assert_eq!(www.result, LookupResult::Safe);
+    assert_eq!(www.ips, vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]);
+    assert_eq!(www.ips, recorded[0].ips);
+    assert_eq!(broken.result, LookupResult::MaybeVulnerable);
+    assert_eq!(broken.response_code, recorded[1].response_code);
+    assert_eq!(unknown.result, LookupResult::LookupError);
+}
+
+#[test]
+fn replay_gives_the_recorded_direct_answers() {
+    let dir = TempDir::new("replay-direct");
+    let options = DirectOptions::default();
+    let ns = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 53));
+    let mut resolver = MockLookup::default();
+    resolver
+        .direct_answers
+        .insert(ns, (ResponseCode::Refused, false));
+
+    let recorder = Recorder::new(resolver, dir.path()).unwrap();
+    let recorded = recorder
+        .query_name_server(ns, "www.example", RecordType::SOA, &options)
+        .unwrap();
+    let other = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 54));
+    assert!(recorder
+        .query_name_server(other, "www.example", RecordType::SOA, &options)
+        .is_err());
+
+    let replayer = Replayer::new(dir.path());
+    let replayed = replayer
+        .query_name_server(ns, "www.example", RecordType::SOA, &options)
+        .unwrap();
+    assert_eq!(replayed.response_code(), ResponseCode::Refused);
+    assert!(!replayed.authoritative());
+    assert_eq!(replayed.queries(), recorded.queries());
+    assert!(replayer
+        .query_name_server(other, "www.example", RecordType::SOA, &options)
+        .is_err());
+    // the lookups and the direct answers are kept apart
+    assert!(replayer.lookup("www.example", RecordType::SOA).is_err());
+}
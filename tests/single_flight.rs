@@ -0,0 +1,32 @@
+mod common;
+
+use common::SlowLookup;
+use nsdetect::lookup::AsyncDnsLookup;
+use nsdetect::single_flight::SingleFlight;
+
+#[test]
+fn concurrent_lookups_of_the_same_name_are_sent_once() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let resolver = SlowLookup::default();
+    let single_flight = SingleFlight::new(&resolver);
+
+    let (first, second, other) = runtime.block_on(async {
+        futures::join!(
+            single_flight.lookup_ip("www.example"),
+            single_flight.lookup_ip("WWW.example"),
+            single_flight.lookup_ip("mail.example"),
+        )
+    });
+    assert_eq!(resolver.queries.get(), 2);
+    assert_eq!(
+        first.unwrap().iter().collect::<Vec<_>>(),
+        second.unwrap().iter().collect::<Vec<_>>()
+    );
+    assert!(other.is_ok());
+
+    // nothing is kept once the lookups are done
+    runtime
+        .block_on(single_flight.lookup_ip("www.example"))
+        .unwrap();
+    assert_eq!(resolver.queries.get(), 3);
+}